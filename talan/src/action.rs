@@ -1,9 +1,35 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+// The language of the game client, which actions have to be typed in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    English,
+    Japanese,
+    German,
+    French,
+}
+
+impl Language {
+    pub const ALL: [Language; 4] = [
+        Language::English,
+        Language::Japanese,
+        Language::German,
+        Language::French,
+    ];
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Action {
+    // The English name, which is also how actions are written in macros.toml.
     pub name: &'static str,
+    pub ja: &'static str,
+    pub de: &'static str,
+    pub fr: &'static str,
     pub wait_ms: u64,
+    // Whether the action increases progress and so can finish a synthesis.
+    pub progress: bool,
 }
 
 // All the current crafting skills in the game minus Collectable Synthesis.
@@ -11,39 +37,88 @@ lazy_static::lazy_static! {
     pub static ref ACTIONS: HashMap<&'static str, Action> = {
         let mut h = HashMap::new();
         // Buff actions
-        h.insert("advanced touch", Action { name: "Advanced Touch",  wait_ms: 2500 });
-        h.insert("basic synthesis", Action { name: "Basic Synthesis",  wait_ms: 2500 });
-        h.insert("basic touch", Action { name: "Basic Touch",  wait_ms: 2500 });
-        h.insert("byregot's blessing", Action { name: "Byregot's Blessing",  wait_ms: 2500 });
-        h.insert("careful observation", Action { name: "Careful Observation",  wait_ms: 2500 });
-        h.insert("careful synthesis", Action { name: "Careful Synthesis",  wait_ms: 2500 });
-        h.insert("delicate synthesis", Action { name: "Delicate Synthesis",  wait_ms: 2500 });
-        h.insert("final appraisal", Action { name: "Final Appraisal",  wait_ms: 1500 });
-        h.insert("focused synthesis", Action { name: "Focused Synthesis",  wait_ms: 2500 });
-        h.insert("focused touch", Action { name: "Focused Touch",  wait_ms: 2500 });
-        h.insert("great strides", Action { name: "Great Strides",  wait_ms: 1500 });
-        h.insert("groundwork", Action { name: "Groundwork",  wait_ms: 2500 });
-        h.insert("hasty touch", Action { name: "Hasty Touch",  wait_ms: 2500 });
-        h.insert("heart and soul", Action { name: "Heart and Soul",  wait_ms: 2500 });
-        h.insert("innovation", Action { name: "Innovation",  wait_ms: 1500 });
-        h.insert("intensive synthesis", Action { name: "Intensive Synthesis",  wait_ms: 2500 });
-        h.insert("manipulation", Action { name: "Manipulation",  wait_ms: 1500 });
-        h.insert("master's mend", Action { name: "Master's Mend",  wait_ms: 2500 });
-        h.insert("muscle memory", Action { name: "Muscle Memory",  wait_ms: 2500 });
-        h.insert("observe", Action { name: "Observe",  wait_ms: 2500 });
-        h.insert("precise touch", Action { name: "Precise Touch",  wait_ms: 2500 });
-        h.insert("preparatory touch", Action { name: "Preparatory Touch",  wait_ms: 2500 });
-        h.insert("prudent synthesis", Action { name: "Prudent Synthesis",  wait_ms: 2500 });
-        h.insert("prudent touch", Action { name: "Prudent Touch",  wait_ms: 2500 });
-        h.insert("rapid synthesis", Action { name: "Rapid Synthesis",  wait_ms: 2500 });
-        h.insert("reflect", Action { name: "Reflect",  wait_ms: 2500 });
-        h.insert("standard touch", Action { name: "Standard Touch",  wait_ms: 2500 });
-        h.insert("trained eye", Action { name: "Trained Eye",  wait_ms: 2500 });
-        h.insert("trained finesse", Action { name: "Trained Finesse",  wait_ms: 2500 });
-        h.insert("tricks of the trade", Action { name: "Tricks of the Trade",  wait_ms: 2500 });
-        h.insert("veneration", Action { name: "Veneration",  wait_ms: 1500 });
-        h.insert("waste not", Action { name: "Waste Not",  wait_ms: 1500 });
-        h.insert("waste not ii", Action { name: "Waste Not II",  wait_ms: 1500 });
+        h.insert("advanced touch", Action { name: "Advanced Touch", ja: "上級加工", de: "Höhere Veredelung", fr: "Ouvrage avancé",  wait_ms: 2500, progress: false });
+        h.insert("basic synthesis", Action { name: "Basic Synthesis", ja: "作業", de: "Bearbeiten", fr: "Travail de base",  wait_ms: 2500, progress: true });
+        h.insert("basic touch", Action { name: "Basic Touch", ja: "加工", de: "Veredelung", fr: "Ouvrage de base",  wait_ms: 2500, progress: false });
+        h.insert("byregot's blessing", Action { name: "Byregot's Blessing", ja: "ビエルゴの祝福", de: "Byregots Benediktion", fr: "Bénédiction de Byregot",  wait_ms: 2500, progress: false });
+        h.insert("careful observation", Action { name: "Careful Observation", ja: "設計変更", de: "Sorgfältige Beobachtung", fr: "Observation méticuleuse",  wait_ms: 2500, progress: false });
+        h.insert("careful synthesis", Action { name: "Careful Synthesis", ja: "模範作業", de: "Sorgfältige Bearbeitung", fr: "Travail prudent",  wait_ms: 2500, progress: true });
+        h.insert("delicate synthesis", Action { name: "Delicate Synthesis", ja: "精密作業", de: "Präzise Bearbeitung", fr: "Travail minutieux",  wait_ms: 2500, progress: true });
+        h.insert("final appraisal", Action { name: "Final Appraisal", ja: "最終確認", de: "Endkontrolle", fr: "Dernière révision",  wait_ms: 1500, progress: false });
+        h.insert("focused synthesis", Action { name: "Focused Synthesis", ja: "注視作業", de: "Fokussierte Bearbeitung", fr: "Travail attentif",  wait_ms: 2500, progress: true });
+        h.insert("focused touch", Action { name: "Focused Touch", ja: "注視加工", de: "Fokussierte Veredelung", fr: "Ouvrage attentif",  wait_ms: 2500, progress: false });
+        h.insert("great strides", Action { name: "Great Strides", ja: "グレートストライド", de: "Große Schritte", fr: "Grands progrès",  wait_ms: 1500, progress: false });
+        h.insert("groundwork", Action { name: "Groundwork", ja: "下地作業", de: "Vorarbeit", fr: "Travail préparatoire",  wait_ms: 2500, progress: true });
+        h.insert("hasty touch", Action { name: "Hasty Touch", ja: "ヘイスティタッチ", de: "Hastige Veredelung", fr: "Ouvrage hâtif",  wait_ms: 2500, progress: false });
+        h.insert("heart and soul", Action { name: "Heart and Soul", ja: "一心不乱", de: "Mit Leib und Seele", fr: "Attention totale",  wait_ms: 2500, progress: false });
+        h.insert("innovation", Action { name: "Innovation", ja: "イノベーション", de: "Innovation", fr: "Innovation",  wait_ms: 1500, progress: false });
+        h.insert("intensive synthesis", Action { name: "Intensive Synthesis", ja: "集中作業", de: "Intensive Bearbeitung", fr: "Travail vigilant",  wait_ms: 2500, progress: true });
+        h.insert("manipulation", Action { name: "Manipulation", ja: "マニピュレーション", de: "Manipulation", fr: "Manipulation",  wait_ms: 1500, progress: false });
+        h.insert("master's mend", Action { name: "Master's Mend", ja: "マスターズメンド", de: "Wiederherstellung", fr: "Réparation de maître",  wait_ms: 2500, progress: false });
+        h.insert("muscle memory", Action { name: "Muscle Memory", ja: "確信", de: "Motorisches Gedächtnis", fr: "Mémoire musculaire",  wait_ms: 2500, progress: true });
+        h.insert("observe", Action { name: "Observe", ja: "経過観察", de: "Beobachten", fr: "Observation",  wait_ms: 2500, progress: false });
+        h.insert("precise touch", Action { name: "Precise Touch", ja: "集中加工", de: "Präzise Veredelung", fr: "Ouvrage précis",  wait_ms: 2500, progress: false });
+        h.insert("preparatory touch", Action { name: "Preparatory Touch", ja: "下地加工", de: "Basisveredelung", fr: "Ouvrage préparatoire",  wait_ms: 2500, progress: false });
+        h.insert("prudent synthesis", Action { name: "Prudent Synthesis", ja: "倹約作業", de: "Umsichtige Bearbeitung", fr: "Travail économe",  wait_ms: 2500, progress: true });
+        h.insert("prudent touch", Action { name: "Prudent Touch", ja: "倹約加工", de: "Umsichtige Veredelung", fr: "Ouvrage économe",  wait_ms: 2500, progress: false });
+        h.insert("rapid synthesis", Action { name: "Rapid Synthesis", ja: "突貫作業", de: "Schnelle Bearbeitung", fr: "Travail rapide",  wait_ms: 2500, progress: true });
+        h.insert("reflect", Action { name: "Reflect", ja: "真価", de: "Einkehr", fr: "Véritable valeur",  wait_ms: 2500, progress: false });
+        h.insert("standard touch", Action { name: "Standard Touch", ja: "中級加工", de: "Solide Veredelung", fr: "Ouvrage standard",  wait_ms: 2500, progress: false });
+        h.insert("trained eye", Action { name: "Trained Eye", ja: "匠の早業", de: "Flinke Hand", fr: "Main preste",  wait_ms: 2500, progress: false });
+        h.insert("trained finesse", Action { name: "Trained Finesse", ja: "匠の絶技", de: "Meisterliche Finesse", fr: "Main experte",  wait_ms: 2500, progress: false });
+        h.insert("tricks of the trade", Action { name: "Tricks of the Trade", ja: "秘訣", de: "Kunstgriff", fr: "Ficelles du métier",  wait_ms: 2500, progress: false });
+        h.insert("veneration", Action { name: "Veneration", ja: "ヴェネレーション", de: "Ehrfurcht", fr: "Vénération",  wait_ms: 1500, progress: false });
+        h.insert("waste not", Action { name: "Waste Not", ja: "倹約", de: "Nachhaltigkeit", fr: "Parcimonie",  wait_ms: 1500, progress: false });
+        h.insert("waste not ii", Action { name: "Waste Not II", ja: "長期倹約", de: "Nachhaltigkeit II", fr: "Parcimonie pérenne",  wait_ms: 1500, progress: false });
+        h
+    };
+
+    // Every action keyed by its lowercased name in each client language.
+    static ref LOCALIZED_ACTIONS: HashMap<String, &'static Action> = {
+        let mut h = HashMap::new();
+        for action in ACTIONS.values() {
+            for name in &[action.name, action.ja, action.de, action.fr] {
+                h.insert(name.to_lowercase(), action);
+            }
+        }
         h
     };
 }
+
+impl Action {
+    // The name to type for this action on a client in |language|.
+    pub fn name_in(&self, language: Language) -> &'static str {
+        match language {
+            Language::English => self.name,
+            Language::Japanese => self.ja,
+            Language::German => self.de,
+            Language::French => self.fr,
+        }
+    }
+}
+
+// Looks up an action by its name in any client language, ignoring case.
+pub fn find_action(name: &str) -> Option<&'static Action> {
+    LOCALIZED_ACTIONS.get(&name.trim().to_lowercase()).copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_action, Language, ACTIONS};
+
+    #[test]
+    fn localized_names() {
+        for action in ACTIONS.values() {
+            for &language in Language::ALL.iter() {
+                let name = action.name_in(language);
+                assert_eq!(find_action(name), Some(action), "{}", name);
+            }
+        }
+        assert_eq!(
+            find_action("ビエルゴの祝福").unwrap().name,
+            "Byregot's Blessing"
+        );
+        assert_eq!(find_action("  VEREDELUNG ").unwrap().name, "Basic Touch");
+        assert!(find_action("Not An Action").is_none());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::task::{Status, Task};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+// Progress through a craft, saved after every item so that a run can be
+// resumed if talan, the game or the PC goes down partway through.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub struct Checkpoint {
+    pub tasks: Vec<Task>,
+    // Items finished for each of |tasks|.
+    pub finished: Vec<u32>,
+    // The job the character was last crafting as, by index into xiv::JOBS.
+    pub job: Option<u32>,
+}
+
+impl Checkpoint {
+    pub fn new(tasks: &[Task], status: &[Status]) -> Self {
+        let finished: Vec<u32> = status.iter().map(|s| s.finished).collect();
+        // Tasks are crafted in order, so the last one with progress is the
+        // one being crafted.
+        let job = tasks
+            .iter()
+            .zip(&finished)
+            .rfind(|(_, &finished)| finished > 0)
+            .map(|(task, _)| task.recipe.job);
+        Checkpoint {
+            tasks: tasks.to_vec(),
+            finished,
+            job,
+        }
+    }
+
+    // The crafts left to do, with finished tasks removed entirely.
+    pub fn remaining_tasks(&self) -> Vec<Task> {
+        self.tasks
+            .iter()
+            .zip(self.finished.iter().chain(std::iter::repeat(&0)))
+            .filter(|(task, &finished)| task.quantity > finished)
+            .map(|(task, &finished)| {
+                let mut task = task.clone();
+                task.finish_crafts(finished);
+                task
+            })
+            .collect()
+    }
+
+    pub fn finished_count(&self) -> u32 {
+        self.finished.iter().sum()
+    }
+}
+
+// The checkpoint is kept next to the config it belongs to.
+pub fn checkpoint_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(CHECKPOINT_FILE)
+}
+
+pub fn read_checkpoint(path: &Path) -> Result<Checkpoint, Error> {
+    Ok(serde_json::from_str::<Checkpoint>(
+        &std::fs::read_to_string(path)?,
+    )?)
+}
+
+// Writes |checkpoint| beside |path| first and moves it into place, so a crash
+// partway through leaves the last checkpoint rather than half of this one.
+pub fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<(), Error> {
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_string_pretty(checkpoint)?.as_bytes())?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+// Removes the checkpoint once there's nothing to resume, which is fine if it
+// was never written.
+pub fn remove_checkpoint(path: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task::test_task;
+
+    #[test]
+    fn resume() -> Result<(), Error> {
+        let tasks = vec![
+            test_task("first", 1, &[], 2),
+            test_task("second", 5, &[], 3),
+            test_task("third", 2, &[], 1),
+        ];
+        let mut status: Vec<Status> = tasks.iter().map(Status::from).collect();
+        status[0].finished = 2;
+        status[1].finished = 1;
+
+        let checkpoint = Checkpoint::new(&tasks, &status);
+        assert_eq!(checkpoint.job, Some(5));
+        assert_eq!(checkpoint.finished_count(), 3);
+        let remaining = checkpoint.remaining_tasks();
+        let remaining: Vec<(&str, u32)> = remaining
+            .iter()
+            .map(|t| (t.recipe.name.as_str(), t.quantity))
+            .collect();
+        assert_eq!(remaining, vec![("second", 2), ("third", 1)]);
+
+        let path =
+            std::env::temp_dir().join(format!("talan-checkpoint-{}.json", std::process::id()));
+        write_checkpoint(&path, &checkpoint)?;
+        assert_eq!(read_checkpoint(&path)?, checkpoint);
+        assert!(!path.with_extension("json.tmp").exists());
+        remove_checkpoint(&path)?;
+        assert!(read_checkpoint(&path).is_err());
+        // Removing it again is fine.
+        remove_checkpoint(&path)
+    }
+}
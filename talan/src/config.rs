@@ -1,9 +1,123 @@
+use crate::action::Language;
+use crate::craft::QUICK_SYNTHESIS_MS;
+use crate::inventory::Inventory;
+use crate::recipe::Recipe;
 use crate::task::Task;
 use anyhow::{Error, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
 
-#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+// Food, medicine or tea that a crafter normally has active.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub struct Consumable {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub hq: bool,
+    // Minutes the buff lasts once used.
+    #[serde(default)]
+    pub duration: i32,
+}
+
+impl Consumable {
+    // Only consumables with both a name and a duration are kept up while crafting.
+    pub fn is_upkept(&self) -> bool {
+        !self.name.is_empty() && self.duration > 0
+    }
+}
+
+pub const CONSUMABLE_LABELS: [&str; 3] = ["Food", "Medicine", "Tea"];
+
+// The stats of a single crafting job. Stats are the values shown on the
+// character sheet while the profile's food and medicine are active.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub struct CrafterProfile {
+    #[serde(default)]
+    pub level: i32,
+    #[serde(default)]
+    pub craftsmanship: i32,
+    #[serde(default)]
+    pub control: i32,
+    #[serde(default)]
+    pub cp: i32,
+    #[serde(default)]
+    pub food: Consumable,
+    #[serde(default)]
+    pub medicine: Consumable,
+    #[serde(default)]
+    pub tea: Consumable,
+}
+
+impl CrafterProfile {
+    // A level of 0 means the profile hasn't been filled out, in which case
+    // every recipe is allowed.
+    pub fn can_craft(&self, recipe: &Recipe) -> bool {
+        self.level == 0 || recipe.level <= self.level as u32
+    }
+
+    // Why |recipe| can't be crafted with this profile, if it can't.
+    pub fn level_warning(&self, recipe: &Recipe) -> Option<String> {
+        if self.can_craft(recipe) {
+            return None;
+        }
+        let job = xiv::JOBS[recipe.job as usize];
+        Some(format!(
+            "{} requires a level {} {}, but your {} is level {}",
+            recipe.name, recipe.level, job, job, self.level
+        ))
+    }
+
+    // The profile's consumables, in the order of CONSUMABLE_LABELS.
+    pub fn consumables(&self) -> [&Consumable; 3] {
+        [&self.food, &self.medicine, &self.tea]
+    }
+}
+
+// Crafts made in a gear set since it was last repaired or had materia
+// extracted from it.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub struct GearWear {
+    #[serde(default)]
+    pub since_repair: u32,
+    #[serde(default)]
+    pub since_extraction: u32,
+}
+
+impl GearWear {
+    // Whether the gear set has been used enough to repair, given the crafts
+    // between repairs. An |interval| of 0 never repairs.
+    pub fn needs_repair(&self, interval: i32) -> bool {
+        interval > 0 && self.since_repair >= interval as u32
+    }
+
+    // Whether the gear set has been used enough to be fully spiritbonded,
+    // given the crafts between extractions. An |interval| of 0 never extracts.
+    pub fn needs_extraction(&self, interval: i32) -> bool {
+        interval > 0 && self.since_extraction >= interval as u32
+    }
+
+    // How many more crafts can be made before a repair or extraction is due,
+    // or None if neither ever is.
+    pub fn crafts_until_due(&self, repair_interval: i32, extraction_interval: i32) -> Option<u32> {
+        let left = |interval: i32, since: u32| {
+            if interval > 0 {
+                Some((interval as u32).saturating_sub(since))
+            } else {
+                None
+            }
+        };
+        match (
+            left(repair_interval, self.since_repair),
+            left(extraction_interval, self.since_extraction),
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Options {
     // Stored as i32 because imgui doesn't bind to unsigned ints.
     #[serde(default)]
@@ -11,11 +125,42 @@ pub struct Options {
     #[serde(default)]
     pub specialist: [bool; xiv::JOB_CNT],
     #[serde(default)]
+    pub profiles: [CrafterProfile; xiv::JOB_CNT],
+    // Wear on each gear set, by its number in |gear|. Jobs that share a gear
+    // set share its wear.
+    #[serde(default)]
+    pub gearset_wear: BTreeMap<i32, GearWear>,
+    // Crafts between self-repairs and materia extractions, where 0 never
+    // does either.
+    #[serde(default)]
+    pub repair_interval: i32,
+    #[serde(default)]
+    pub extraction_interval: i32,
+    #[serde(default)]
     pub should_clear_window_on_craft: bool,
     #[serde(default)]
     pub remove_finished_tasks: bool,
-    #[serde(default)]
+    // Only read from older configs, where trial synthesis was one option
+    // for every task rather than set on each.
+    #[serde(default, skip_serializing)]
     pub use_trial_synthesis: bool,
+    // Limit crafts to the materials in the config's inventory, keeping it
+    // up to date as they're used.
+    #[serde(default)]
+    pub track_inventory: bool,
+    // Craft tasks in the planner's order, leaving the list as it is.
+    #[serde(default)]
+    pub optimize_task_order: bool,
+    // Actions are typed in the client's language.
+    #[serde(default)]
+    pub language: Language,
+    // Milliseconds each item of a quick synthesis batch is waited on for.
+    #[serde(default = "default_quick_synthesis_ms")]
+    pub quick_synthesis_ms: i32,
+}
+
+fn default_quick_synthesis_ms() -> i32 {
+    QUICK_SYNTHESIS_MS as i32
 }
 
 impl Default for Options {
@@ -23,9 +168,17 @@ impl Default for Options {
         Options {
             gear: [0; xiv::JOB_CNT],
             specialist: [false; xiv::JOB_CNT],
+            profiles: Default::default(),
+            gearset_wear: BTreeMap::new(),
+            repair_interval: 0,
+            extraction_interval: 0,
             use_trial_synthesis: false,
+            optimize_task_order: false,
+            track_inventory: false,
             should_clear_window_on_craft: true,
             remove_finished_tasks: true,
+            language: Language::English,
+            quick_synthesis_ms: default_quick_synthesis_ms(),
         }
     }
 }
@@ -44,6 +197,8 @@ pub struct Config {
     pub tasks: Vec<Task>,
     #[serde(default)]
     pub macros: Vec<Macro>,
+    #[serde(default)]
+    pub inventory: Inventory,
 }
 
 pub const DEFAULT_CONFIG_FILE: &str = "config.json";
@@ -62,9 +217,18 @@ pub fn get_config(path: Option<&Path>) -> Config {
 
 // Reads the config from disk. Returns a default config if one cannot be found.
 pub fn read_config(path: &Path) -> Result<Config, Error> {
-    Ok(serde_json::from_str::<Config>(&std::fs::read_to_string(
-        path,
-    )?)?)
+    parse_config(&std::fs::read_to_string(path)?)
+}
+
+fn parse_config(text: &str) -> Result<Config, Error> {
+    let mut config = serde_json::from_str::<Config>(text)?;
+    if config.options.use_trial_synthesis {
+        config.options.use_trial_synthesis = false;
+        for task in &mut config.tasks {
+            task.trial_synthesis = true;
+        }
+    }
+    Ok(config)
 }
 
 // Writes |cfg| to disk.
@@ -86,4 +250,71 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn profile_level() {
+        let recipe = Recipe {
+            name: "Cloud Pearl".to_string(),
+            level: 80,
+            ..Default::default()
+        };
+        let mut profile = CrafterProfile::default();
+        assert!(profile.can_craft(&recipe));
+        profile.level = 79;
+        assert!(!profile.can_craft(&recipe));
+        profile.level = 80;
+        assert!(profile.can_craft(&recipe));
+        assert_eq!(profile.level_warning(&recipe), None);
+        profile.level = 79;
+        assert_eq!(
+            profile.level_warning(&recipe).as_deref(),
+            Some("Cloud Pearl requires a level 80 CRP, but your CRP is level 79")
+        );
+    }
+
+    #[test]
+    fn consumable_upkeep() {
+        let mut profile = CrafterProfile::default();
+        assert!(profile.consumables().iter().all(|c| !c.is_upkept()));
+        profile.tea.name = "Cunning Craftsman's Tea".to_string();
+        assert!(!profile.tea.is_upkept());
+        profile.tea.duration = 15;
+        assert!(profile.tea.is_upkept());
+        assert_eq!(profile.consumables()[2], &profile.tea);
+    }
+
+    #[test]
+    fn gear_wear() {
+        let mut wear = GearWear {
+            since_repair: 50,
+            since_extraction: 50,
+        };
+        assert!(!wear.needs_repair(0));
+        assert!(!wear.needs_extraction(0));
+        assert!(wear.needs_repair(50));
+        assert!(!wear.needs_repair(51));
+        assert!(!wear.needs_extraction(51));
+        wear.since_extraction += 1;
+        assert!(wear.needs_extraction(51));
+
+        assert_eq!(wear.crafts_until_due(0, 0), None);
+        assert_eq!(wear.crafts_until_due(60, 0), Some(10));
+        assert_eq!(wear.crafts_until_due(60, 55), Some(4));
+        assert_eq!(wear.crafts_until_due(40, 0), Some(0));
+    }
+
+    #[test]
+    fn trial_synthesis_option() -> Result<(), Error> {
+        let mut config = Config::default();
+        config.tasks.push(Task::new(Recipe::default(), 1));
+        let mut json = serde_json::to_value(&config)?;
+        assert!(json["options"].get("use_trial_synthesis").is_none());
+
+        // The old option carries over to every task.
+        json["options"]["use_trial_synthesis"] = true.into();
+        let config = parse_config(&json.to_string())?;
+        assert!(!config.options.use_trial_synthesis);
+        assert!(config.tasks[0].trial_synthesis);
+        Ok(())
+    }
 }
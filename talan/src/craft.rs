@@ -1,54 +1,362 @@
 //use crate::role_actions::RoleActions;
-use crate::action::Action;
-use crate::config::Options;
-use crate::macros::Macro;
+use crate::config::{Consumable, GearWear, Options, CONSUMABLE_LABELS};
+use crate::history::{ActionTiming, CraftRecord, Outcome};
+use crate::input::{Input, Key};
+use crate::inventory::Inventory;
+use crate::macros::{Macro, Step};
+use crate::session::{Session, SessionEnd, SessionLimits};
 use crate::task;
-use anyhow::Error;
-use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 // Milliseconds to pad the GCD to account for latency
 const GCD_PADDING: u64 = 250;
+// The most items the Quick Synthesis dialog accepts at once.
+pub const QUICK_SYNTHESIS_BATCH: u32 = 99;
+// Milliseconds a single item takes to quick synthesize by default, erring on
+// the long side.
+pub const QUICK_SYNTHESIS_MS: u64 = 4000;
+// Milliseconds a consumable's buff must outlast the next craft by, to cover
+// navigation and latency.
+const BUFF_MARGIN_MS: u64 = 15000;
+// Gear slots listed by the materia extraction window, soul crystal excluded.
+const EQUIPMENT_SLOTS: usize = 12;
+// How often a paused craft checks whether it's been resumed.
+const PAUSE_POLL: Duration = Duration::from_millis(100);
 
-pub struct Crafter<'a, C, S>
+// The buff from a consumable that's been used this craft.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Buff {
+    // One of CONSUMABLE_LABELS.
+    pub label: &'static str,
+    pub name: String,
+    pub expires: Instant,
+}
+
+// The reasons a craft can stop before every task is finished.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CraftError {
+    // There's no gear set configured for the job, by index into xiv::JOBS.
+    MissingGearSet(u32),
+    // The game window couldn't be found or has closed.
+    WindowLost,
+    // The user asked to stop.
+    Cancelled,
+    // The task's macro no longer exists.
+    MacroMissing(String),
+    // The game window stopped accepting input.
+    InputFailed,
+    // A step was asked for that can't follow the one before it.
+    UnexpectedState(CraftState, CraftState),
+}
+
+impl fmt::Display for CraftError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CraftError::MissingGearSet(job) => {
+                write!(
+                    f,
+                    "No gear set is configured for {}",
+                    xiv::JOBS[*job as usize]
+                )
+            }
+            CraftError::WindowLost => write!(
+                f,
+                "Unable to find the XIV window. Is Final Fantasy XIV running?"
+            ),
+            CraftError::Cancelled => write!(f, "Crafting was stopped"),
+            CraftError::MacroMissing(name) => write!(f, "Macro '{}' no longer exists", name),
+            CraftError::InputFailed => write!(f, "The XIV window stopped accepting input"),
+            CraftError::UnexpectedState(from, to) => {
+                write!(f, "Crafting got lost going from {:?} to {:?}", from, to)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CraftError {}
+
+// Where the crafter has left the game's UI. Every step of a craft moves from
+// one of these to the next, so the crafter knows where the cursor is rather
+// than assuming it, and a step taken from the wrong place is caught.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CraftState {
+    // Nothing is open, as far as we know.
+    Idle,
+    // The gear set for a job, by index into xiv::JOBS, was just equipped.
+    JobSwapped(u32),
+    // The crafting log is open on the current job's page.
+    LogOpen,
+    // A recipe is selected, with the cursor on its Synthesize button.
+    RecipeSelected,
+    // Its materials have been picked, with the cursor back on Synthesize.
+    MaterialsSet,
+    // A synthesis, or a quick synthesis batch, is underway.
+    Synthesizing,
+    // A synthesis ended and the log is back with the recipe and materials
+    // still picked and the cursor on Synthesize.
+    Finished,
+    // Getting back to Idle after something went wrong.
+    Recovering,
+}
+
+impl CraftState {
+    // Whether the crafter can go straight from this state to |next|.
+    pub fn leads_to(self, next: CraftState) -> bool {
+        use CraftState::*;
+        match (self, next) {
+            // Anything can go wrong, but recovering only ever ends up Idle.
+            (_, Recovering) | (Recovering, Idle) => true,
+            (Idle, JobSwapped(_)) | (JobSwapped(_), JobSwapped(_)) => true,
+            (Idle, LogOpen) | (JobSwapped(_), LogOpen) => true,
+            (LogOpen, RecipeSelected) | (RecipeSelected, MaterialsSet) => true,
+            (RecipeSelected, Synthesizing) | (MaterialsSet, Synthesizing) => true,
+            (Finished, Synthesizing) | (Synthesizing, Finished) => true,
+            // Closing the crafting log. Quick synthesis closes it once the
+            // batch's results are dismissed.
+            (LogOpen, Idle) | (RecipeSelected, Idle) | (MaterialsSet, Idle) => true,
+            (Finished, Idle) | (Synthesizing, Idle) => true,
+            _ => false,
+        }
+    }
+}
+
+// What the client wants from the craft, asked between actions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Control {
+    Continue,
+    // Wait at the next point between syntheses until resumed.
+    Pause,
+    Resume,
+    Stop,
+}
+
+// Changes to the character the client should know about, beyond task progress.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    // The consumable buffs applied so far.
+    Buffs(Vec<Buff>),
+    // A macro craft finished or was stopped partway.
+    Craft(CraftRecord),
+    // The craft is waiting to be resumed, or has been.
+    Paused(bool),
+    // The crafter moved on to a new state.
+    State(CraftState),
+    // A session limit was reached, so the craft stopped before the next synthesis.
+    SessionEnded(SessionEnd),
+    // Materials were used up, or added by crafting them.
+    Inventory(Inventory),
+    // A gear set was crafted with, repaired or had materia extracted.
+    Wear { gearset: i32, wear: GearWear },
+}
+
+pub struct Crafter<'a, C, S, E>
 where
-    C: FnMut() -> bool,
+    C: FnMut() -> Control,
     S: FnMut(&[task::Status]),
+    E: FnMut(Event),
 {
-    // TODO: Consolidate xiv::XivHandle and xiv::Process
-    handle: xiv::XivHandle,
+    input: &'a mut dyn Input,
     options: &'a Options,
     macros: &'a [Macro],
     tasks: &'a [task::Task],
     status_fn: S,
     continue_fn: C,
+    event_fn: E,
+    // The buffs we've applied, indexed like CrafterProfile::consumables().
+    buffs: [Option<Buff>; 3],
+    // What's left of the materials, if they're being tracked.
+    inventory: Option<Inventory>,
+    // Wear on each gear set, starting from what the options had saved.
+    gearset_wear: BTreeMap<i32, GearWear>,
+    // The task index and item number currently being crafted.
+    position: Option<(usize, u32)>,
+    // The actions sent by the last macro and how long each took.
+    action_timings: Vec<ActionTiming>,
+    // When the current craft began, for its history record.
+    craft_started: Instant,
+    // Set when a pause was asked for, until the craft gets to pause.
+    pause_requested: bool,
+    // What's left of the session's limits, and whether one has been reached.
+    session: Session,
+    session_ended: bool,
+    // Where the game's UI is and when it got there.
+    state: CraftState,
+    state_since: Instant,
 }
 
-impl<'a, C, S> Crafter<'a, C, S>
+impl<'a, C, S, E> Crafter<'a, C, S, E>
 where
-    C: FnMut() -> bool,
+    C: FnMut() -> Control,
     S: FnMut(&[task::Status]),
+    E: FnMut(Event),
 {
     pub fn new(
-        handle: xiv::XivHandle,
+        input: &'a mut dyn Input,
         options: &'a Options,
         macros: &'a [Macro],
         tasks: &'a [task::Task],
         status_fn: S,
         continue_fn: C,
-    ) -> Result<Self, Error> {
-        Ok(Crafter {
-            handle,
+        event_fn: E,
+    ) -> Self {
+        let now = input.now();
+        Crafter {
+            input,
             options,
             macros,
             tasks,
             status_fn,
             continue_fn,
+            event_fn,
+            buffs: Default::default(),
+            inventory: None,
+            gearset_wear: options.gearset_wear.clone(),
+            position: None,
+            action_timings: Vec::new(),
+            craft_started: now,
+            pause_requested: false,
+            session: Session::unlimited(),
+            session_ended: false,
+            state: CraftState::Idle,
+            state_since: now,
+        }
+    }
+
+    // Limits crafts to the materials in |inventory|, keeping it up to date as
+    // they're used.
+    pub fn with_inventory(mut self, inventory: Option<Inventory>) -> Self {
+        self.inventory = inventory;
+        self
+    }
+
+    // Stops the craft cleanly once |limits| are reached, counting from now.
+    pub fn with_limits(mut self, limits: &SessionLimits) -> Self {
+        self.session = Session::new(limits, self.input.now());
+        self
+    }
+
+    // The task and item number the craft was on, if it got as far as a task.
+    pub fn position(&self) -> Option<(&task::Task, u32)> {
+        self.position.map(|(i, item)| (&self.tasks[i], item))
+    }
+
+    // Whether to carry on, remembering any pause for the next point it's
+    // safe to wait at.
+    fn should_continue(&mut self) -> bool {
+        match (self.continue_fn)() {
+            Control::Continue => true,
+            Control::Pause => {
+                self.pause_requested = true;
+                true
+            }
+            Control::Resume => {
+                self.pause_requested = false;
+                true
+            }
+            Control::Stop => false,
+        }
+    }
+
+    // Waits out a pause, if one was asked for. This is only called between
+    // syntheses, so the recipe and materials picked in the crafting log are
+    // still there to carry on with.
+    fn pause_point(&mut self) -> Result<(), CraftError> {
+        if !self.pause_requested {
+            return Ok(());
+        }
+        log::info!("paused");
+        self.input.note("paused");
+        (self.event_fn)(Event::Paused(true));
+        let paused = self.input.now();
+        loop {
+            match (self.continue_fn)() {
+                Control::Stop => return Err(CraftError::Cancelled),
+                Control::Resume => break,
+                // Waiting for the user is real time even in a dry run, whose
+                // clock only moves with the input it plans.
+                _ => std::thread::sleep(PAUSE_POLL),
+            }
+        }
+        log::info!("resumed");
+        self.pause_requested = false;
+        // Time spent paused isn't part of the craft.
+        self.craft_started += self.input.now() - paused;
+        (self.event_fn)(Event::Paused(false));
+        Ok(())
+    }
+
+    // Makes sure the game is still there to take input before continuing.
+    fn check_game(&mut self) -> Result<(), CraftError> {
+        if !self.should_continue() {
+            return Err(CraftError::Cancelled);
+        }
+        self.pause_point()?;
+        self.input.check()
+    }
+
+    // Does |act| to move from the current state to |next|, provided |next|
+    // can follow it.
+    fn transition<T>(
+        &mut self,
+        next: CraftState,
+        act: impl FnOnce(&mut Self) -> T,
+    ) -> Result<T, CraftError> {
+        if !self.state.leads_to(next) {
+            log::error!("can't go from {:?} to {:?}", self.state, next);
+            return Err(CraftError::UnexpectedState(self.state, next));
+        }
+        let result = act(self);
+        self.enter(next);
+        Ok(result)
+    }
+
+    fn enter(&mut self, next: CraftState) {
+        let now = self.input.now();
+        log::info!(
+            "{:?} -> {:?} after {:?}",
+            self.state,
+            next,
+            now - self.state_since
+        );
+        self.state = next;
+        self.state_since = now;
+        (self.event_fn)(Event::State(next));
+    }
+
+    // Gets the game back to Idle from wherever the crafter was left.
+    fn recover(&mut self) {
+        log::warn!("recovering from {:?}", self.state);
+        self.enter(CraftState::Recovering);
+        self.input.note("clear the game window");
+        self.input.clear_window();
+        self.enter(CraftState::Idle);
+    }
+
+    // Closes the crafting log.
+    fn close_log(&mut self) -> Result<(), CraftError> {
+        self.transition(CraftState::Idle, |c| {
+            c.input.press(Key::Escape);
+            c.input.wait(2.0);
         })
     }
 
-    // Craft all the configured tasks and update the client by way of |status_callback|.
-    pub fn craft_items(&mut self) -> Result<(), Error> {
+    // Craft all the configured tasks and update the client by way of
+    // |status_callback|. A craft that fails partway is recovered from so the
+    // game is left Idle, unless the user stopped it or the game went away.
+    pub fn craft_items(&mut self) -> Result<(), CraftError> {
+        let result = self.craft_tasks();
+        match &result {
+            Err(CraftError::Cancelled) | Err(CraftError::WindowLost) => (),
+            Err(_) if self.state != CraftState::Idle => self.recover(),
+            _ => (),
+        }
+        result
+    }
+
+    fn craft_tasks(&mut self) -> Result<(), CraftError> {
         // Initialize the crafting status and send an initialize slice
         // so the UI knows what to start rendering.
         let mut status: Vec<task::Status> = self.tasks.iter().map(task::Status::from).collect();
@@ -57,7 +365,7 @@ where
         //handle.use_slow_navigation = options.use_slow_dialog_navigation;
         if self.options.should_clear_window_on_craft {
             // Get the UI into a state we can trust it, and pray the user doesn't touch it.
-            xiv::ui::clear_window(self.handle);
+            self.input.clear_window();
         }
 
         // Clear role actions before we iterate tasks so the game state
@@ -67,39 +375,75 @@ where
             log::trace!("Task: {:?}", task);
             let task_job: usize = task.recipe.job as usize;
 
+            self.position = Some((i, 1));
             if self.options.gear[task_job] == 0 {
-                panic!(
-                    "No gear set is configured for {}, aborting tasks!",
-                    xiv::JOBS[task_job]
-                );
+                return Err(CraftError::MissingGearSet(task.recipe.job));
+            }
+            self.check_game()?;
+            if self.session_room(task) == 0 {
+                return Ok(());
+            }
+            // Checked before swapping jobs, which would be wasted on a task
+            // that can't be crafted.
+            if self.craftable(task, 0) == 0 {
+                self.out_of_materials(task, &mut status, i);
+                continue;
             }
 
             // Swap our job if necessary. It may have been used in the previous task.
             if job != task.recipe.job {
                 log::trace!("changing job to {}.", xiv::JOBS[task_job]);
                 log::info!("changing to gearset {}", self.options.gear[task_job]);
-                xiv::ui::press_enter(self.handle);
-                xiv::ui::send_string(
-                    self.handle,
-                    &format!("/gearset change {}", self.options.gear[task_job]),
-                );
-                xiv::ui::wait(0.5);
-                xiv::ui::press_enter(self.handle);
-                // If we don't wait here we might bring the window up before
-                // the job has changed, leading to the wrong class seeding the
-                // window's mode.
-                xiv::ui::wait(1.0);
+                let gear = self.options.gear[task_job];
+                self.transition(CraftState::JobSwapped(task.recipe.job), |c| {
+                    c.input.note(&format!(
+                        "change to gearset {} ({})",
+                        gear,
+                        xiv::JOBS[task_job]
+                    ));
+                    c.input.press(Key::Enter);
+                    c.input.send_string(&format!("/gearset change {}", gear));
+                    c.input.wait(0.5);
+                    c.input.press(Key::Enter);
+                    // If we don't wait here we might bring the window up before
+                    // the job has changed, leading to the wrong class seeding the
+                    // window's mode.
+                    c.input.wait(1.0);
+                })?;
 
                 job = task.recipe.job;
             } else {
                 log::trace!("already {}, no need to change job.", xiv::JOBS[task_job]);
             }
 
+            if task.quick_synthesis && !task.trial_synthesis && task.can_quick_synthesize() {
+                self.quick_synthesize_task(task, &mut status, i)?;
+                if self.session_ended {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            let steps = match task.macro_index(self.macros) {
+                Some(i) => &self.macros[i].steps[..],
+                None => return Err(CraftError::MacroMissing(task.macro_name.clone())),
+            };
+            let craft_ms: u64 = steps
+                .iter()
+                .take_while(|step| **step != Step::Finish)
+                .map(Step::duration_ms)
+                .sum();
+            let margin = Duration::from_millis(craft_ms + BUFF_MARGIN_MS);
+            self.upkeep(task_job, margin);
+
             // Navigate to the correct recipe based on the index provided
-            self.select_recipe(task);
+            self.select_recipe(task)?;
 
-            if !self.options.use_trial_synthesis {
-                self.select_materials(task);
+            // The materials currently picked in the crafting log.
+            // Trial syntheses don't use materials, so none are picked.
+            let mut selected = task.materials_for(0);
+            if !task.trial_synthesis {
+                self.select_materials(task, &selected)?;
             }
             for task_index in 1..=task.quantity {
                 log::info!(
@@ -108,91 +452,496 @@ where
                     task_index,
                     task.quantity
                 );
-                // Time to craft the items
-                if !(self.continue_fn)()
-                    || !self.execute_task(&self.macros[task.macro_id as usize].actions[..])
+                self.position = Some((i, task_index));
+                self.check_game()?;
+                if self.craftable(task, task_index - 1) == 0 {
+                    self.out_of_materials(task, &mut status, i);
+                    break;
+                }
+                if self.session_room(task) == 0 {
+                    return self.close_log();
+                }
+                self.input.note(&format!(
+                    "craft {} {}/{}",
+                    task.recipe.name, task_index, task.quantity
+                ));
+                let materials = task.materials_for(task_index - 1);
+                let change_materials =
+                    task.specify_materials && !task.trial_synthesis && materials != selected;
+                if change_materials
+                    || self.needs_consumables(task_job, margin)
+                    || self.needs_maintenance(task_job)
                 {
+                    // Items and gear can't be used with the crafting log open,
+                    // so it's closed and the recipe selected again afterwards.
+                    // That also puts the materials back to NQ to pick again.
+                    self.close_log()?;
+                    self.upkeep(task_job, margin);
+                    self.select_recipe(task)?;
+                    selected = materials;
+                    if !task.trial_synthesis {
+                        self.select_materials(task, &selected)?;
+                    }
+                }
+                // Time to craft the items
+                let completed = self.execute_task(steps, task)?;
+                let outcome = match (completed, task.trial_synthesis, task.is_collectable()) {
+                    (false, _, _) => Outcome::Stopped,
+                    (true, true, _) => Outcome::Trial,
+                    (true, false, false) => Outcome::Completed,
+                    (true, false, true) => Outcome::Collected,
+                };
+                self.record_craft(task, outcome);
+                if !completed {
                     log::info!("Received stop order");
-                    return Ok(());
+                    return Err(CraftError::Cancelled);
                 }
                 status[i].finished += 1;
+                if outcome == Outcome::Collected {
+                    status[i].collectables += 1;
+                }
                 (self.status_fn)(&status[..]);
+                // A trial leaves the gear and materials as they were.
+                if !task.trial_synthesis {
+                    self.add_wear(task_job, 1);
+                    self.use_materials(task, task_index - 1, 1);
+                }
+                self.session.add_crafts(1);
                 // Check if we received a message to stop from the main thread.
-                xiv::ui::wait(2.0);
+                self.input.wait(2.0);
+                // The next craft's time runs from here, taking in any
+                // navigation to get to it.
+                self.craft_started = self.input.now();
             }
 
-            xiv::ui::press_escape(self.handle);
-            xiv::ui::wait(2.0);
+            self.close_log()?;
         }
 
         Ok(())
     }
 
-    fn open_craft_window(&self) {
-        xiv::ui::send_key(self.handle, 'N' as i32);
-        xiv::ui::wait(1.0);
+    // Uses |job|'s consumables and looks after its gear as needed. The next
+    // craft's time runs from afterwards, since none of this is part of it.
+    // The crafting log must be closed.
+    fn upkeep(&mut self, job: usize, margin: Duration) {
+        self.use_consumables(job, margin);
+        self.maintain_gear(job);
+        self.craft_started = self.input.now();
+    }
+
+    // Whether consumable |i| of |consumable| has to be used again to last
+    // until |deadline|.
+    fn buff_expiring(&self, i: usize, consumable: &Consumable, deadline: Instant) -> bool {
+        if !consumable.is_upkept() {
+            return false;
+        }
+        match &self.buffs[i] {
+            // Using a different item replaces the buff, so it has to be ours.
+            Some(buff) => buff.name != consumable.name || buff.expires < deadline,
+            None => true,
+        }
+    }
+
+    // Whether any of |job|'s consumables is missing or would wear off within |margin|.
+    fn needs_consumables(&self, job: usize, margin: Duration) -> bool {
+        let deadline = self.input.now() + margin;
+        self.options.profiles[job]
+            .consumables()
+            .iter()
+            .enumerate()
+            .any(|(i, consumable)| self.buff_expiring(i, consumable, deadline))
+    }
+
+    // Uses each of |job|'s consumables that is missing or would wear off
+    // within |margin|. The crafting log must be closed.
+    fn use_consumables(&mut self, job: usize, margin: Duration) {
+        let deadline = self.input.now() + margin;
+        let options = self.options;
+        let mut used = false;
+        for (i, consumable) in options.profiles[job].consumables().iter().enumerate() {
+            if !self.buff_expiring(i, consumable, deadline) {
+                continue;
+            }
+            log::info!("using {}", consumable.name);
+            self.input.note(&format!("use {}", consumable.name));
+            self.input.press(Key::Enter);
+            self.input.send_string(&item_command(consumable));
+            self.input.press(Key::Enter);
+            // Wait out the item's animation before doing anything else.
+            self.input.wait(3.0);
+            self.buffs[i] = Some(Buff {
+                label: CONSUMABLE_LABELS[i],
+                name: consumable.name.clone(),
+                expires: self.input.now() + Duration::from_secs(consumable.duration as u64 * 60),
+            });
+            used = true;
+        }
+        if used {
+            let buffs = self.buffs.iter().flatten().cloned().collect();
+            (self.event_fn)(Event::Buffs(buffs));
+        }
+    }
+
+    // How many more times |task| can be crafted with the materials left, after
+    // |done| of its crafts. When the inventory isn't tracked it's assumed to
+    // have everything, but a task spreading HQ still stops where its stock does.
+    fn craftable(&self, task: &task::Task, done: u32) -> u32 {
+        let stock = task.stock_craftable().saturating_sub(done);
+        self.inventory
+            .as_ref()
+            .map_or(stock, |inventory| min(stock, inventory.craftable(task)))
+    }
+
+    // How many more syntheses of |task| the session has room for, going by
+    // the task's estimate. Running out ends the session.
+    fn session_room(&mut self, task: &task::Task) -> u32 {
+        let craft_ms = u64::from(task.estimate / task.quantity.max(1));
+        match self.session.room(self.input.now(), craft_ms) {
+            (0, Some(reason)) => {
+                log::info!("session over ({:?}) before {}", reason, task.recipe.name);
+                self.input.note(&format!("session over ({:?})", reason));
+                self.session_ended = true;
+                (self.event_fn)(Event::SessionEnded(reason));
+                0
+            }
+            (room, _) => room,
+        }
+    }
+
+    // Sends the history record of the craft of |task| that just ended.
+    fn record_craft(&mut self, task: &task::Task, outcome: Outcome) {
+        let actions = std::mem::take(&mut self.action_timings);
+        let duration_ms = (self.input.now() - self.craft_started).as_millis() as u64;
+        let record = craft_record(task, actions, duration_ms, outcome);
+        (self.event_fn)(Event::Craft(record));
+    }
+
+    // Sends a history record for each of the |count| items of the quick
+    // synthesis batch of |task| that just ended, sharing its time out evenly.
+    fn record_batch(&mut self, task: &task::Task, count: u32) {
+        let count = u64::from(count.max(1));
+        let duration_ms = (self.input.now() - self.craft_started).as_millis() as u64 / count;
+        let actions: Vec<ActionTiming> = std::mem::take(&mut self.action_timings)
+            .into_iter()
+            .map(|action| ActionTiming {
+                ms: action.ms / count,
+                ..action
+            })
+            .collect();
+        for _ in 0..count {
+            let record = craft_record(task, actions.clone(), duration_ms, Outcome::Completed);
+            (self.event_fn)(Event::Craft(record));
+        }
+    }
+
+    // Takes what |count| crafts of |task|, starting with craft |first|, used
+    // out of the inventory.
+    fn use_materials(&mut self, task: &task::Task, first: u32, count: u32) {
+        if let Some(inventory) = &mut self.inventory {
+            for craft in first..first + count {
+                inventory.consume(task, craft);
+            }
+            inventory.produce(task, count);
+            (self.event_fn)(Event::Inventory(inventory.clone()));
+        }
+    }
+
+    // Marks task |i| as stopped for lack of materials.
+    fn out_of_materials(&mut self, task: &task::Task, status: &mut [task::Status], i: usize) {
+        log::warn!(
+            "out of materials for {} after {}/{}",
+            task.recipe.name,
+            status[i].finished,
+            task.quantity
+        );
+        self.input
+            .note(&format!("out of materials for {}", task.recipe.name));
+        status[i].out_of_materials = true;
+        (self.status_fn)(status);
+    }
+
+    fn wear(&self, job: usize) -> GearWear {
+        let gearset = self.options.gear[job];
+        self.gearset_wear.get(&gearset).copied().unwrap_or_default()
+    }
+
+    // Whether |job|'s gear set is due a repair or materia extraction.
+    fn needs_maintenance(&self, job: usize) -> bool {
+        let wear = self.wear(job);
+        wear.needs_repair(self.options.repair_interval)
+            || wear.needs_extraction(self.options.extraction_interval)
+    }
+
+    // How many crafts |job|'s gear set can make before it's due a repair or
+    // materia extraction, if it ever is.
+    fn crafts_until_maintenance(&self, job: usize) -> Option<u32> {
+        self.wear(job).crafts_until_due(
+            self.options.repair_interval,
+            self.options.extraction_interval,
+        )
+    }
+
+    fn set_wear(&mut self, job: usize, wear: GearWear) {
+        let gearset = self.options.gear[job];
+        self.gearset_wear.insert(gearset, wear);
+        (self.event_fn)(Event::Wear { gearset, wear });
+    }
+
+    // Counts |crafts| more crafts against |job|'s gear set.
+    fn add_wear(&mut self, job: usize, crafts: u32) {
+        let mut wear = self.wear(job);
+        wear.since_repair += crafts;
+        wear.since_extraction += crafts;
+        self.set_wear(job, wear);
+    }
+
+    // Repairs |job|'s gear set and extracts its materia if either is due.
+    // The crafting log must be closed.
+    fn maintain_gear(&mut self, job: usize) {
+        let mut wear = self.wear(job);
+        if wear.needs_repair(self.options.repair_interval) {
+            log::info!("repairing gear after {} crafts", wear.since_repair);
+            self.repair_gear();
+            wear.since_repair = 0;
+        }
+        if wear.needs_extraction(self.options.extraction_interval) {
+            log::info!("extracting materia after {} crafts", wear.since_extraction);
+            self.extract_materia();
+            wear.since_extraction = 0;
+        }
+        if wear != self.wear(job) {
+            self.set_wear(job, wear);
+        }
+    }
+
+    // Self-repairs all equipped gear.
+    fn repair_gear(&mut self) {
+        self.input.note("repair gear");
+        self.input.press(Key::Enter);
+        self.input.send_string("/generalaction \"Repair\"");
+        self.input.press(Key::Enter);
+        self.input.wait(1.0);
+        // The repair window opens on Repair All, which asks for confirmation.
+        self.input.press(Key::Confirm);
+        self.input.wait(0.5);
+        self.input.press(Key::Confirm);
+        self.input.wait(3.0);
+        self.input.press(Key::Escape);
+        self.input.wait(1.0);
+    }
+
+    // Extracts materia from each piece of fully spiritbonded gear.
+    fn extract_materia(&mut self) {
+        self.input.note("extract materia");
+        self.input.press(Key::Enter);
+        self.input
+            .send_string("/generalaction \"Materia Extraction\"");
+        self.input.press(Key::Enter);
+        self.input.wait(1.0);
+        // Walk every equipment slot. Confirming a piece that isn't fully
+        // spiritbonded does nothing, so only the ready ones are extracted.
+        for _ in 0..EQUIPMENT_SLOTS {
+            self.input.press(Key::Confirm);
+            self.input.wait(0.5);
+            self.input.press(Key::Confirm);
+            self.input.wait(3.0);
+            self.input.press(Key::Down);
+        }
+        self.input.press(Key::Escape);
+        self.input.wait(1.0);
+    }
+
+    // Crafts |task| through the Quick Synthesis dialog in batches no larger than
+    // the game allows, updating status |i| after each one.
+    fn quick_synthesize_task(
+        &mut self,
+        task: &task::Task,
+        status: &mut [task::Status],
+        i: usize,
+    ) -> Result<(), CraftError> {
+        let job = task.recipe.job as usize;
+        let mut remaining = task.quantity;
+        while remaining > 0 {
+            self.position = Some((i, status[i].finished + 1));
+            self.check_game()?;
+            let batch = min(
+                min(remaining, QUICK_SYNTHESIS_BATCH),
+                self.craftable(task, task.quantity - remaining),
+            );
+            if batch == 0 {
+                self.out_of_materials(task, status, i);
+                return Ok(());
+            }
+            let batch = min(batch, self.session_room(task));
+            if batch == 0 {
+                return Ok(());
+            }
+            self.upkeep(job, Duration::from_millis(BUFF_MARGIN_MS));
+            // A batch stops short of the next repair or extraction, which
+            // then happens before the one after.
+            let batch = match self.crafts_until_maintenance(job) {
+                Some(left) => min(batch, left.max(1)),
+                None => batch,
+            };
+            log::info!(
+                "quick synthesizing {}x {} ({} left)",
+                batch,
+                task.recipe.name,
+                remaining
+            );
+            self.select_recipe(task)?;
+            // There's no telling how much of a stopped batch was made, so
+            // none of it is counted and it's left for next time.
+            if !self.quick_synthesize(batch)? {
+                self.record_craft(task, Outcome::Stopped);
+                return Err(CraftError::Cancelled);
+            }
+            self.record_batch(task, batch);
+            let first = task.quantity - remaining;
+            remaining -= batch;
+            status[i].finished += batch;
+            (self.status_fn)(status);
+            self.add_wear(job, batch);
+            self.use_materials(task, first, batch);
+            self.session.add_crafts(batch);
+        }
+        Ok(())
+    }
+
+    // Quick synthesizes |count| items starting from the Synthesize button of the
+    // selected recipe, then dismisses the results and closes the crafting log.
+    // Returns false if crafting was stopped before the batch finished.
+    fn quick_synthesize(&mut self, count: u32) -> Result<bool, CraftError> {
+        self.transition(CraftState::Synthesizing, |c| {
+            c.input.note(&format!("quick synthesize {}", count));
+            // Quick Synthesis is the button to the left of Synthesize.
+            c.input.press(Key::Left);
+            c.input.press(Key::Confirm);
+            c.input.wait(1.0);
+            // The dialog opens with the quantity field selected, which we type over.
+            c.input.press(Key::Confirm);
+            c.input.press(Key::Backspace);
+            c.input.press(Key::Backspace);
+            c.input.send_string(&count.to_string());
+            c.input.press(Key::Enter);
+            // Down to the dialog's Synthesize button.
+            c.input.press(Key::Down);
+            c.input.press(Key::Confirm);
+        })?;
+
+        // Nothing tells us when the batch is done, so it's given a conservative
+        // estimate of the time each item takes, which can be tuned in the options.
+        let started = self.input.now();
+        let item_ms = self.options.quick_synthesis_ms.max(0) as u64;
+        let done = started + Duration::from_millis(u64::from(count) * item_ms);
+        let mut completed = true;
+        while self.input.now() < done {
+            if !self.should_continue() {
+                completed = false;
+                break;
+            }
+            self.input.wait(1.0);
+        }
+        // The batch's synthesis counts as a single action for the history.
+        self.action_timings = vec![ActionTiming {
+            action: "Quick Synthesis".to_string(),
+            ms: (self.input.now() - started).as_millis() as u64,
+        }];
+
+        // The results window's Quit button also stops a batch that's still
+        // going. Quitting brings back the crafting log, which is closed so the
+        // next recipe can be selected from scratch.
+        self.transition(CraftState::Idle, |c| {
+            c.input.press(Key::Confirm);
+            c.input.wait(3.0);
+            c.input.press(Key::Escape);
+            c.input.wait(2.0);
+            completed
+        })
+    }
+
+    fn open_craft_window(&mut self) -> Result<(), CraftError> {
+        self.transition(CraftState::LogOpen, |c| {
+            c.input.press(Key::CraftingLog);
+            c.input.wait(1.0);
+        })
     }
 
     // Selects the appropriate recipe then leaves the cursor on the Synthesize
     // button, ready for material selection.
-    fn select_recipe(&self, task: &task::Task) {
+    fn select_recipe(&mut self, task: &task::Task) -> Result<(), CraftError> {
         // Bring up the crafting window itself and give it time to appear
-        self.open_craft_window();
+        self.open_craft_window()?;
         log::info!("selecting recipe...");
-        // The crafting window always starts with the current job selected and if we press
-        // |BACK| 1 more time than the job's index then we will end up at the search box.
-        for _ in 0..=task.recipe.job + 1 {
-            xiv::ui::cursor_backward(self.handle);
-        }
-        xiv::ui::press_confirm(self.handle);
-        xiv::ui::wait(1.0);
-        xiv::ui::send_string(self.handle, &task.recipe.name);
-        xiv::ui::press_enter(self.handle);
-        xiv::ui::wait(1.0);
-        // Navigate to the offset we need
-        for _ in 0..task.recipe.index {
-            xiv::ui::cursor_down(self.handle);
-        }
+        self.transition(CraftState::RecipeSelected, |c| {
+            c.input.note(&format!("select recipe {}", task.recipe.name));
+            // The crafting window always starts with the current job selected and if we press
+            // |BACK| 1 more time than the job's index then we will end up at the search box.
+            for _ in 0..=task.recipe.job + 1 {
+                c.input.press(Key::Backward);
+            }
+            c.input.press(Key::Confirm);
+            c.input.wait(1.0);
+            c.input.send_string(&task.recipe.name);
+            c.input.press(Key::Enter);
+            c.input.wait(1.0);
+            // Navigate to the offset we need
+            for _ in 0..task.recipe.index {
+                c.input.press(Key::Down);
+            }
 
-        // Select the recipe to get to components / synthesize button
-        xiv::ui::press_confirm(self.handle);
+            // Select the recipe to get to components / synthesize button
+            c.input.press(Key::Confirm);
+        })
     }
 
-    fn select_any_materials(&self, task: &task::Task) {
+    // Picks every HQ material there is before making up the rest with NQ.
+    fn select_any_materials(&mut self, task: &task::Task) {
         // Up to the icon for the bottom material
-        xiv::ui::cursor_up(self.handle);
+        self.input.press(Key::Up);
         // Right to the NQ column
-        xiv::ui::cursor_right(self.handle);
+        self.input.press(Key::Right);
         // Right to the HQ column
-        xiv::ui::cursor_right(self.handle);
+        self.input.press(Key::Right);
 
         // The cursor should be on the quantity field of the bottom item now
         // We move through the ingredients backwards because we start at the bottom of t
         for (i, material) in task.recipe.mats.iter().rev().enumerate() {
             log::debug!("{}x {}", material.count, material.name);
             for _ in 0..material.count {
-                xiv::ui::press_confirm(self.handle)
+                self.input.press(Key::Confirm)
             }
             // Don't move up if we've made it back to the top of the ingredients
             if i != task.recipe.mats.len() - 1 {
-                xiv::ui::cursor_up(self.handle);
+                self.input.press(Key::Up);
             }
         }
-        xiv::ui::cursor_left(self.handle);
+        self.input.press(Key::Left);
         for material in &task.recipe.mats {
             for _ in 0..material.count {
-                xiv::ui::press_confirm(self.handle)
+                self.input.press(Key::Confirm)
             }
-            xiv::ui::cursor_down(self.handle);
+            self.input.press(Key::Down);
         }
     }
 
-    fn select_materials(&self, task: &task::Task) {
-        if !task.specify_materials {
-            return self.select_any_materials(task);
-        }
+    fn select_materials(
+        &mut self,
+        task: &task::Task,
+        materials: &[task::MaterialCount],
+    ) -> Result<(), CraftError> {
+        self.transition(CraftState::MaterialsSet, |c| {
+            c.input.note("select materials");
+            if task.specify_materials {
+                c.select_hq_materials(materials);
+            } else {
+                c.select_any_materials(task);
+            }
+        })
+    }
 
-        let mut hq_mats = task.mat_quality.iter().fold(0, |acc, &mat| acc + mat.hq);
+    fn select_hq_materials(&mut self, materials: &[task::MaterialCount]) {
+        let mut hq_mats = materials.iter().fold(0, |acc, &mat| acc + mat.hq);
         // If there are no HQ mats we can fast path this by just
         // starting the synthesis.
         if hq_mats == 0 {
@@ -200,82 +949,497 @@ where
         }
 
         // Up to the icon for the bottom material
-        xiv::ui::cursor_up(self.handle);
+        self.input.press(Key::Up);
         // Right to the NQ column
-        xiv::ui::cursor_right(self.handle);
+        self.input.press(Key::Right);
         // Right to the HQ column
-        xiv::ui::cursor_right(self.handle);
+        self.input.press(Key::Right);
 
         // Move up the HQ column and increase the HQ count per the task
         // values. Once there are none left we can shortcut back to the
         // confirm button.
-        for (i, mq) in task.mat_quality.iter().rev().enumerate() {
+        for (i, mq) in materials.iter().rev().enumerate() {
             for _ in 0..mq.hq {
-                xiv::ui::press_confirm(self.handle);
+                self.input.press(Key::Confirm);
             }
 
             hq_mats -= mq.hq;
             if hq_mats > 0 {
-                xiv::ui::cursor_up(self.handle);
+                self.input.press(Key::Up);
             } else {
                 for _ in 0..=i {
-                    xiv::ui::cursor_down(self.handle);
+                    self.input.press(Key::Down);
                 }
                 break;
             }
         }
     }
 
-    fn execute_task(&mut self, actions: &[&'static Action]) -> bool {
-        // If we're at the start of a task we will already have the Synthesize button
-        // selected with the pointer.
-        // TODO: Trial synthesis code should be here.
-        if self.options.use_trial_synthesis {
-            xiv::ui::cursor_left(self.handle);
-            xiv::ui::cursor_left(self.handle);
-        }
-        xiv::ui::press_confirm(self.handle);
+    // Runs |steps| as a synthesis of |task|, returning false if it was
+    // stopped partway.
+    fn execute_task(&mut self, steps: &[Step], task: &task::Task) -> Result<bool, CraftError> {
+        let trial = task.trial_synthesis;
+        let collectable = task.is_collectable() && !trial;
+        // The Synthesize button is selected with the pointer in any state a
+        // synthesis can start from, with Trial Synthesis two to its left.
+        self.transition(CraftState::Synthesizing, |c| {
+            if trial {
+                c.input.press(Key::Left);
+                c.input.press(Key::Left);
+            }
+            c.input.press(Key::Confirm);
+        })?;
 
         // The first action is one second off so we start typing while the
         // crafting window is coming up.
         // TODO: Wait until State::ReadyForActions
-        let mut next_action = Instant::now() + Duration::from_secs(2);
+        let mut next_action = self.input.now() + Duration::from_secs(2);
         let mut prev_action = next_action;
-        for action in actions {
-            if !(self.continue_fn)() {
-                return false;
+        self.action_timings.clear();
+        for step in steps {
+            if !self.should_continue() {
+                return Ok(false);
             }
 
-            xiv::ui::press_enter(self.handle);
-            xiv::ui::send_string(self.handle, &format!("/ac \"{}\"", &action.name));
+            let (action, wait_ms) = match *step {
+                Step::Action { action, wait_ms } => (action, wait_ms),
+                Step::Pause(ms) => {
+                    log::debug!("pausing {}ms", ms);
+                    next_action += Duration::from_millis(ms);
+                    continue;
+                }
+                Step::Finish => {
+                    log::debug!("craft finished by directive");
+                    break;
+                }
+            };
+
+            self.input.press(Key::Enter);
+            let name = action.name_in(self.options.language);
+            self.input.send_string(&format!("/ac \"{}\"", name));
             // At this point the action is queued in the text buffer, so we can
             // wait the GCD duration based on the last action we sent.
-            let mut now = Instant::now();
+            let mut now = self.input.now();
             if now < next_action {
                 let delta = next_action - now;
                 log::trace!("sleeping {:?}", delta);
-                sleep(delta);
+                self.input.sleep(delta);
             }
-            xiv::ui::press_enter(self.handle);
-            now = Instant::now();
+            self.input.press(Key::Enter);
+            now = self.input.now();
             log::debug!("action: {} ({:?})", action.name, now - prev_action);
+            // An action's time runs until the next one is sent.
+            if let Some(last) = self.action_timings.last_mut() {
+                last.ms = (now - prev_action).as_millis() as u64;
+            }
+            self.action_timings.push(ActionTiming {
+                action: action.name.to_string(),
+                ms: 0,
+            });
 
             // TODO: Instead of this, wait for the step to increase
             prev_action = now;
-            next_action = now + Duration::from_millis(action.wait_ms + GCD_PADDING);
+            // A <wait.N> from the macro already allows for latency like it
+            // would in-game, so it isn't padded.
+            let wait = wait_ms.unwrap_or(action.wait_ms + GCD_PADDING);
+            next_action = now + Duration::from_millis(wait);
         }
 
-        if !(self.continue_fn)() {
-            return false;
+        if !self.should_continue() {
+            return Ok(false);
         }
 
         // Wait for the last GCD to finish
-        sleep(next_action - Instant::now());
+        let now = self.input.now();
+        self.input.sleep(next_action.saturating_duration_since(now));
+        if let Some(last) = self.action_timings.last_mut() {
+            last.ms = (self.input.now() - prev_action).as_millis() as u64;
+        }
+
+        let collectable_delay = Duration::from_millis(task.collectable_delay as u64);
+        self.transition(CraftState::Finished, |c| {
+            c.input.wait(3.0);
+            if trial {
+                // Nothing is made, so instead of the result the game says
+                // the trial is over before the crafting log returns.
+                log::debug!("dismissing trial synthesis notice");
+                c.input.press(Key::Confirm);
+                c.input.wait(1.0);
+            } else if collectable {
+                // Collectables ask to be accepted before the crafting log returns,
+                // which takes a little longer than the normal result.
+                log::debug!("accepting collectable");
+                c.input.press(Key::Confirm);
+                c.input.sleep(collectable_delay);
+            }
+            // At the end of this sequence the cursor should have selected the recipe
+            // again and be on the Synthesize button.
+            c.input.press(Key::Confirm);
+            true
+        })
+    }
+}
+
+fn craft_record(
+    task: &task::Task,
+    actions: Vec<ActionTiming>,
+    duration_ms: u64,
+    outcome: Outcome,
+) -> CraftRecord {
+    CraftRecord {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        recipe_id: task.recipe.id,
+        recipe_name: task.recipe.name.clone(),
+        job: task.recipe.job,
+        // Quick Synthesis doesn't use the task's macro.
+        macro_name: if task.quick_synthesis {
+            String::new()
+        } else {
+            task.macro_name.clone()
+        },
+        actions,
+        duration_ms,
+        outcome,
+    }
+}
+
+// The chat command that uses |consumable|, asking for the HQ item when set.
+fn item_command(consumable: &Consumable) -> String {
+    if consumable.hq {
+        format!("/item \"{}\" hq", consumable.name)
+    } else {
+        format!("/item \"{}\"", consumable.name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::{Plan, Recorder};
+    use crate::macros::read_macros_from_buffer;
+    use crate::recipe::Recipe;
+    use std::cell::RefCell;
+
+    // Dry runs two crafts of a one action macro within |limits|, with an
+    // empty inventory that only matters if the recipe needs materials. Each of
+    // |controls| is answered once, the first time the crafter asks after its
+    // event was sent. Returns the items finished, the events sent and the plan.
+    fn craft(controls: &[(Event, Control)], limits: &SessionLimits) -> (u32, Vec<Event>, Plan) {
+        let (result, finished, events, plan) = craft_with(|_, _| (), controls, limits);
+        result.unwrap();
+        (finished, events, plan)
+    }
+
+    // The same as craft(), with the task and options changed by |configure|
+    // first and the craft's result returned as well.
+    fn craft_with(
+        configure: impl FnOnce(&mut task::Task, &mut Options),
+        controls: &[(Event, Control)],
+        limits: &SessionLimits,
+    ) -> (Result<(), CraftError>, u32, Vec<Event>, Plan) {
+        const MACROS: &str = r#"
+            [[xiv_macro]]
+            name = "one step"
+            durability = [ 40 ]
+            actions = "/ac \"Basic Synthesis\""
+        "#;
+        let mut macros = Vec::new();
+        read_macros_from_buffer(MACROS, &mut macros).unwrap();
+        let recipe = Recipe {
+            name: "Cloud Pearl".to_string(),
+            job: 5,
+            ..Default::default()
+        };
+        let mut task = task::Task::new(recipe, 2);
+        task.macro_name = "one step".to_string();
+        let mut options = Options::default();
+        options.gear[5] = 3;
+        configure(&mut task, &mut options);
+        let tasks = vec![task];
+
+        let mut recorder = Recorder::new();
+        let events = RefCell::new(Vec::new());
+        let mut finished = 0;
+        let mut answered = vec![false; controls.len()];
+        let continue_fn = || {
+            let events = events.borrow();
+            for ((trigger, control), answered) in controls.iter().zip(answered.iter_mut()) {
+                if !*answered && events.contains(trigger) {
+                    *answered = true;
+                    return *control;
+                }
+            }
+            Control::Continue
+        };
+        let result = Crafter::new(
+            &mut recorder,
+            &options,
+            &macros,
+            &tasks,
+            |status: &[task::Status]| finished = status[0].finished,
+            continue_fn,
+            |event| events.borrow_mut().push(event),
+        )
+        .with_inventory(Some(Inventory::default()))
+        .with_limits(limits)
+        .craft_items();
+        (result, finished, events.into_inner(), recorder.finish())
+    }
+
+    fn states(events: &[Event]) -> Vec<CraftState> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                Event::State(state) => Some(*state),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn craft_durations(events: &[Event]) -> Vec<u64> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Craft(record) => Some(record.duration_ms),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pause() {
+        // The pause comes in partway through the first synthesis, so it
+        // waits at the second item until resumed.
+        let (finished, events, plan) = craft(
+            &[
+                (Event::State(CraftState::Synthesizing), Control::Pause),
+                (Event::Paused(true), Control::Resume),
+            ],
+            &SessionLimits::default(),
+        );
+        assert_eq!(finished, 2);
+        let paused: Vec<&Event> = events
+            .iter()
+            .filter(|e| matches!(e, Event::Paused(_)))
+            .collect();
+        assert_eq!(paused, vec![&Event::Paused(true), &Event::Paused(false)]);
+        let notes: Vec<&str> = plan
+            .entries
+            .iter()
+            .map(|e| e.text.as_str())
+            .filter(|&t| t.starts_with("-- craft") || t == "-- paused")
+            .collect();
+        assert_eq!(
+            notes,
+            vec![
+                "-- craft Cloud Pearl 1/2",
+                "-- paused",
+                "-- craft Cloud Pearl 2/2"
+            ]
+        );
+
+        // Time spent paused isn't counted against the craft, or the plan.
+        let (_, unpaused, unpaused_plan) = craft(&[], &SessionLimits::default());
+        assert_eq!(plan.total, unpaused_plan.total);
+        assert_eq!(craft_durations(&events), craft_durations(&unpaused));
+    }
+
+    #[test]
+    fn session_limits() {
+        let limits = SessionLimits {
+            max_crafts: Some(1),
+            ..Default::default()
+        };
+        let (finished, events, _) = craft(&[], &limits);
+        assert_eq!(finished, 1);
+        assert!(events.contains(&Event::SessionEnded(SessionEnd::Crafts)));
+
+        // Without time for a synthesis nothing is started at all.
+        let limits = SessionLimits {
+            max_duration: Some(Duration::from_secs(0)),
+            ..Default::default()
+        };
+        let (finished, events, plan) = craft(&[], &limits);
+        assert_eq!(finished, 0);
+        assert_eq!(events, vec![Event::SessionEnded(SessionEnd::Time)]);
+        assert!(plan.entries.iter().all(|e| !e.text.starts_with("/gearset")));
+    }
+
+    #[test]
+    fn transitions() {
+        use CraftState::*;
+        let (_, events, _) = craft(&[], &SessionLimits::default());
+        assert_eq!(
+            states(&events),
+            vec![
+                JobSwapped(5),
+                LogOpen,
+                RecipeSelected,
+                MaterialsSet,
+                Synthesizing,
+                Finished,
+                Synthesizing,
+                Finished,
+                Idle
+            ]
+        );
+
+        assert!(Finished.leads_to(Synthesizing));
+        assert!(JobSwapped(5).leads_to(Recovering));
+        assert!(!Idle.leads_to(Synthesizing));
+        assert!(!LogOpen.leads_to(MaterialsSet));
+        assert!(!Recovering.leads_to(LogOpen));
+    }
+
+    #[test]
+    fn recover() {
+        use CraftState::*;
+        // The macro goes missing once the job has been changed, which leaves
+        // the game to be cleared back to Idle.
+        let (result, finished, events, plan) = craft_with(
+            |task, _| task.macro_name = "missing".to_string(),
+            &[],
+            &SessionLimits::default(),
+        );
+        assert_eq!(result, Err(CraftError::MacroMissing("missing".to_string())));
+        assert_eq!(finished, 0);
+        assert_eq!(states(&events), vec![JobSwapped(5), Recovering, Idle]);
+        assert!(plan
+            .entries
+            .iter()
+            .any(|e| e.text == "-- clear the game window"));
+
+        // Stopping leaves things as they are.
+        let (result, _, events, _) = craft_with(
+            |_, _| (),
+            &[(Event::State(CraftState::Synthesizing), Control::Stop)],
+            &SessionLimits::default(),
+        );
+        assert_eq!(result, Err(CraftError::Cancelled));
+        assert_eq!(states(&events).last(), Some(&Synthesizing));
+    }
+
+    #[test]
+    fn trial_synthesis() {
+        let (result, finished, events, plan) = craft_with(
+            |task, _| {
+                task.trial_synthesis = true;
+                task.specify_materials = true;
+            },
+            &[],
+            &SessionLimits::default(),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(finished, 2);
+        let outcomes: Vec<Outcome> = events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Craft(record) => Some(record.outcome),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(outcomes, vec![Outcome::Trial, Outcome::Trial]);
+        // Materials are never picked, and the gear isn't worn.
+        assert!(!states(&events).contains(&CraftState::MaterialsSet));
+        assert!(plan.entries.iter().all(|e| e.text != "-- select materials"));
+        assert!(!events.iter().any(|e| matches!(e, Event::Wear { .. })));
+    }
+
+    #[test]
+    fn quick_synthesis_stopped() {
+        let (result, finished, events, _) = craft_with(
+            |task, _| task.quick_synthesis = true,
+            &[(Event::State(CraftState::Synthesizing), Control::Stop)],
+            &SessionLimits::default(),
+        );
+        assert_eq!(result, Err(CraftError::Cancelled));
+        // Nothing from the stopped batch is taken as made.
+        assert_eq!(finished, 0);
+        assert!(!events.iter().any(|e| matches!(e, Event::Wear { .. })));
+    }
+
+    #[test]
+    fn out_of_materials() {
+        let (result, finished, _, plan) = craft_with(
+            |task, _| {
+                task.recipe.mats.push(crate::recipe::RecipeMaterial {
+                    count: 1,
+                    name: "Pearl Sand".to_string(),
+                })
+            },
+            &[],
+            &SessionLimits::default(),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(finished, 0);
+        // The job isn't changed for a task there's nothing to craft with.
+        assert!(plan.entries.iter().all(|e| !e.text.starts_with("/gearset")));
+    }
+
+    #[test]
+    fn quick_synthesis_maintenance() {
+        let (result, finished, events, plan) = craft_with(
+            |task, options| {
+                task.quantity = 12;
+                task.quick_synthesis = true;
+                options.repair_interval = 5;
+            },
+            &[],
+            &SessionLimits::default(),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(finished, 12);
+        // Every item is in the history.
+        assert_eq!(craft_durations(&events).len(), 12);
+        // Batches stop at each repair rather than going past it.
+        let batches: Vec<&str> = plan
+            .entries
+            .iter()
+            .map(|e| e.text.as_str())
+            .filter(|t| t.starts_with("-- quick") || *t == "-- repair gear")
+            .collect();
+        assert_eq!(
+            batches,
+            vec![
+                "-- quick synthesize 5",
+                "-- repair gear",
+                "-- quick synthesize 5",
+                "-- repair gear",
+                "-- quick synthesize 2"
+            ]
+        );
+        assert_eq!(
+            events
+                .iter()
+                .rev()
+                .find(|e| matches!(e, Event::Wear { .. })),
+            Some(&Event::Wear {
+                gearset: 3,
+                wear: GearWear {
+                    since_repair: 2,
+                    since_extraction: 12
+                }
+            })
+        );
+    }
 
-        // At the end of this sequence the cursor should have selected the recipe
-        // again and be on the Synthesize button.
-        xiv::ui::wait(3.0);
-        xiv::ui::press_confirm(self.handle);
-        true
+    #[test]
+    fn craft_durations_exclude_upkeep() {
+        // The first craft doesn't take in changing jobs, and the second
+        // doesn't take in the repair before it, so both take as long.
+        let (_, events, _) = craft(&[], &SessionLimits::default());
+        let (result, _, repaired, plan) = craft_with(
+            |_, options| options.repair_interval = 1,
+            &[],
+            &SessionLimits::default(),
+        );
+        assert_eq!(result, Ok(()));
+        assert!(plan.entries.iter().any(|e| e.text == "-- repair gear"));
+        let durations = craft_durations(&repaired);
+        assert_eq!(durations.len(), 2);
+        assert_eq!(durations[0], durations[1]);
+        assert_eq!(durations[0], craft_durations(&events)[0]);
     }
 }
@@ -1,13 +1,27 @@
-use crate::config::{self, write_config};
+use crate::action::Language;
+use crate::checkpoint::{checkpoint_path, read_checkpoint, remove_checkpoint, Checkpoint};
+use crate::config::{self, write_config, GearWear, CONSUMABLE_LABELS};
+use crate::craft::{Buff, CraftError, CraftState};
+use crate::history::{history_path, read_history, Stats, Timings};
+use crate::input::{format_elapsed, Plan};
+use crate::inventory::import_inventory_from_clipboard;
 use crate::lists::import_tasks_from_clipboard;
-use crate::macros::{get_macro_for_recipe, read_macros_from_file, Macro};
-use crate::rpc::{Request, Response};
-use crate::task::{Status, Task};
+use crate::macros::{
+    append_macros_to_file, copy_to_clipboard, export_macro, import_rotations_from_clipboard,
+    rank_macros, read_macros_from_file, Diagnostic, ExportOptions, Macro, Severity,
+};
+use crate::planner::{job_changes, plan_order};
+use crate::recipe::Recipe;
+use crate::rpc::{CraftRequest, Request, Response};
+use crate::session::{parse_deadline, SessionEnd, SessionLimits};
+use crate::task::{HqSpread, MaterialCount, Status, Task};
+use crate::watch::FileWatcher;
 use std::path::PathBuf;
 
 use imgui::*;
 use std::cmp::{max, min};
 use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant, SystemTime};
 
 // These represent a ratio compared to WINDOW_W and WINDOW_H
 const CONFIGURATION_SIZE: [f32; 2] = [300.0, 0.0];
@@ -22,10 +36,66 @@ struct ModalText {
     msg: ImString,
 }
 
+/// The session limits as entered in the session window.
+#[derive(Debug)]
+struct SessionSettings {
+    use_deadline: bool,
+    // A 24 hour "HH:MM" time.
+    deadline: ImString,
+    use_duration: bool,
+    minutes: i32,
+    use_crafts: bool,
+    crafts: i32,
+}
+
+impl Default for SessionSettings {
+    fn default() -> SessionSettings {
+        let mut deadline = ImString::with_capacity(16);
+        deadline.push_str("23:00");
+        SessionSettings {
+            use_deadline: false,
+            deadline,
+            use_duration: false,
+            minutes: 60,
+            use_crafts: false,
+            crafts: 100,
+        }
+    }
+}
+
+impl SessionSettings {
+    fn limits(&self) -> Result<SessionLimits, String> {
+        let deadline = if self.use_deadline {
+            match parse_deadline(self.deadline.to_str(), &chrono::Local::now()) {
+                Some(deadline) => Some(SystemTime::from(deadline)),
+                None => return Err(format!("\"{}\" isn't a time like 23:00", self.deadline)),
+            }
+        } else {
+            None
+        };
+        Ok(SessionLimits {
+            deadline,
+            max_duration: if self.use_duration {
+                Some(Duration::from_secs(max(self.minutes, 0) as u64 * 60))
+            } else {
+                None
+            },
+            max_crafts: if self.use_crafts {
+                Some(max(self.crafts, 0) as u32)
+            } else {
+                None
+            },
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum WorkerStatus {
     Idle,
     Crafting,
+    // Asked to pause, which happens once the current synthesis is done.
+    Pausing,
+    Paused,
     Stopping,
 }
 
@@ -44,17 +114,60 @@ struct UiState {
     previous_window_size: [f32; 2],
     worker: WorkerStatus,
     craft_status: Option<Vec<Status>>,
+    // Where each task of the running craft is in the task list, in the
+    // order they're crafted.
+    craft_order: Vec<usize>,
+    // Consumable buffs the crafter has applied, for showing their time left.
+    buffs: Vec<Buff>,
     /// Store for the Error / Message popup
     modal_popup: ModalText,
     // The item search string.
     search_str: ImString,
     // The job dropdown selection.
     macros: Vec<Macro>,
+    // Problems found the last time macros were loaded.
+    macro_diagnostics: Vec<Diagnostic>,
+    // Watches the macro file and its includes so they can be reloaded on change.
+    macro_watcher: FileWatcher,
+    // Tasks whose macro changed on the last reload, as (task, old, new) names.
+    macro_changes: Vec<(String, String, String)>,
     search_job: usize,
     show_gear_set_window: bool,
+    show_macro_diagnostics_window: bool,
+    show_macro_export_window: bool,
+    show_order_window: bool,
+    show_inventory_window: bool,
+    show_stats_window: bool,
+    show_session_window: bool,
+    session: SessionSettings,
+    // When the running craft's session has to end by, and how many items it
+    // may craft, for the progress window.
+    session_end: Option<Instant>,
+    session_crafts: Option<u32>,
+    // Where the crafter has the game's UI and since when, so a step that
+    // gets stuck shows up.
+    craft_state: Option<(CraftState, Instant)>,
+    // Totals and averages from the craft history, read at startup and after
+    // each craft.
+    stats: Stats,
+    timings: Timings,
+    // The name of the item being added in the inventory window.
+    new_item_name: ImString,
+    // The macro selected in the export window and how to export it.
+    export_macro_id: usize,
+    export_options: ExportOptions,
+    // The job whose crafter profile is shown in the gear window.
+    profile_job: usize,
     task_list_modification: Option<TaskListModification>,
     should_load_macros: bool,
     should_exit: bool,
+    // Whether the last craft changed the inventory, which needs saving.
+    config_changed: bool,
+    // Whether the worker is on a dry run, and the plan from the last one.
+    dry_run: bool,
+    dry_run_plan: Option<Plan>,
+    // Progress from a craft that was interrupted, until the user resumes or discards it.
+    checkpoint: Option<Checkpoint>,
 }
 
 impl Default for UiState {
@@ -68,13 +181,38 @@ impl Default for UiState {
             },
             worker: WorkerStatus::Idle,
             macros: Vec::new(),
+            macro_diagnostics: Vec::new(),
+            macro_watcher: FileWatcher::default(),
+            macro_changes: Vec::new(),
             craft_status: None,
+            craft_order: Vec::new(),
+            buffs: Vec::new(),
             search_str: ImString::with_capacity(128),
             search_job: 0,
             show_gear_set_window: false,
+            show_macro_diagnostics_window: false,
+            show_macro_export_window: false,
+            show_order_window: false,
+            show_inventory_window: false,
+            show_stats_window: false,
+            show_session_window: false,
+            session: SessionSettings::default(),
+            session_end: None,
+            session_crafts: None,
+            craft_state: None,
+            stats: Stats::default(),
+            timings: Timings::default(),
+            new_item_name: ImString::with_capacity(128),
+            export_macro_id: 0,
+            export_options: ExportOptions::default(),
+            profile_job: 0,
             task_list_modification: None,
             should_load_macros: false,
             should_exit: false,
+            config_changed: false,
+            dry_run: false,
+            dry_run_plan: None,
+            checkpoint: None,
         }
     }
 }
@@ -121,8 +259,18 @@ impl<'a, 'b> Gui<'a> {
             "Talan",
         );
 
-        // Always load macros on the first frame.
+        // Always load macros on the first frame, which estimates the tasks
+        // from the history.
         self.state.should_load_macros = true;
+        self.load_history();
+        // A checkpoint is only left behind if the last craft never finished.
+        let path = checkpoint_path(&self.config_path);
+        if path.exists() {
+            match read_checkpoint(&path) {
+                Ok(checkpoint) => self.state.checkpoint = Some(checkpoint),
+                Err(e) => log::error!("Unable to read {}: {}", path.display(), e),
+            }
+        }
         system.main_loop(|run, ui| {
             if self.state.should_exit {
                 *run = false;
@@ -130,22 +278,14 @@ impl<'a, 'b> Gui<'a> {
             }
 
             // If necessary, macros are loaded from the file before the next frame.
-            if self.state.should_load_macros {
-                // Load the macros and remap any tasks that need it.
-                self.state.macros.clear();
-                match read_macros_from_file(&self.macro_path, &mut self.state.macros) {
-                    Ok(()) => {
-                        // Load saved tasks and re-map the macros in case the macro file changed.
-                        for task in &mut config.tasks {
-                            task.macro_id = get_macro_for_recipe(
-                                &self.state.macros,
-                                &task.recipe,
-                                config.options.specialist[task.recipe.job as usize],
-                            );
-                        }
-                    }
-                    Err(e) => log::error!("Failed to load macros: {}", e),
-                }
+            // Reloads wait until the worker is idle so the macros of a running
+            // craft never change underneath it.
+            if self.state.macro_watcher.poll() {
+                log::info!("Macro file changed, reloading.");
+                self.state.should_load_macros = true;
+            }
+            if self.state.should_load_macros && self.state.worker == WorkerStatus::Idle {
+                self.load_macros(config);
                 self.state.should_load_macros = false;
             }
 
@@ -156,15 +296,23 @@ impl<'a, 'b> Gui<'a> {
                 match resp {
                     Response::Recipe { recipe, count } => {
                         if let Some(r) = recipe {
-                            let craft_cnt = (count as f32 / r.result_amount as f32).ceil() as u32;
-                            let mut task = Task::new(r, craft_cnt);
-                            task.macro_id = get_macro_for_recipe(
-                                &self.state.macros,
-                                &task.recipe,
-                                config.options.specialist[task.recipe.job as usize],
-                            );
-                            task.update_estimate(&self.state.macros);
-                            config.tasks.push(task);
+                            let job = r.job as usize;
+                            match config.options.profiles[job].level_warning(&r) {
+                                Some(msg) => {
+                                    Gui::set_modal_text(&mut self.state, "Level too low", &msg)
+                                }
+                                None => {
+                                    let craft_cnt =
+                                        (count as f32 / r.result_amount as f32).ceil() as u32;
+                                    let mut task = Task::new(r, craft_cnt);
+                                    task.select_macro(
+                                        &self.state.macros,
+                                        &config.options,
+                                        &self.state.timings,
+                                    );
+                                    config.tasks.push(task);
+                                }
+                            }
                         } else {
                             let msg = &format!(
                                 "No {} results found on XIVApi for \"{}\"",
@@ -178,33 +326,102 @@ impl<'a, 'b> Gui<'a> {
                         // There is a final status sent when the worker is told to stop,
                         // before the EOW. This lets us track the final item completion
                         // and reflect it in the progress bars, but we need to stay out
-                        // of the crafting state to hide the |Stop| button.
-                        if self.state.worker != WorkerStatus::Stopping {
+                        // of the crafting state to hide the |Stop| button. A pause
+                        // that was asked for is still coming, too.
+                        if self.state.worker == WorkerStatus::Idle {
                             self.state.worker = WorkerStatus::Crafting;
                         }
                         self.state.craft_status = Some(status);
                     }
+                    Response::Buffs(buffs) => self.state.buffs = buffs,
+                    Response::State(state) => {
+                        self.state.craft_state = Some((state, Instant::now()))
+                    }
+                    Response::SessionEnded(reason) => {
+                        let msg = match reason {
+                            SessionEnd::Time => "There wasn't time left for another synthesis.",
+                            SessionEnd::Crafts => "The session's crafts are all done.",
+                        };
+                        Gui::set_modal_text(&mut self.state, "Session finished", msg);
+                    }
+                    Response::Paused(paused) => {
+                        if self.state.worker != WorkerStatus::Stopping {
+                            self.state.worker = if paused {
+                                WorkerStatus::Paused
+                            } else {
+                                WorkerStatus::Crafting
+                            };
+                        }
+                    }
+                    // Wear is saved as it changes so the counts survive talan
+                    // or the PC going down mid-craft.
+                    Response::Wear { gearset, wear } => {
+                        config.options.gearset_wear.insert(gearset, wear);
+                        if let Err(e) = write_config(Some(&self.config_path), config) {
+                            log::error!("Failed to save gear wear: {}", e);
+                        }
+                    }
+                    Response::Inventory(inventory) => {
+                        config.inventory = inventory;
+                        self.state.config_changed = true;
+                    }
+                    Response::CraftError { error, task, item } => {
+                        // Stopping was asked for, so there's nothing to explain.
+                        if error != CraftError::Cancelled {
+                            let msg = match task {
+                                Some(task) => format!("{}\n\nStopped at {} #{}", error, task, item),
+                                None => error.to_string(),
+                            };
+                            Gui::set_modal_text(&mut self.state, "Crafting stopped", &msg);
+                        }
+                    }
+                    Response::DryRun(plan) => self.state.dry_run_plan = Some(plan),
                     Response::Eow => {
-                        // prune any completed tasks.
-                        if config.options.remove_finished_tasks {
+                        // prune any completed tasks, which a dry run didn't really craft.
+                        if config.options.remove_finished_tasks && !self.state.dry_run {
                             if let Some(status) = &self.state.craft_status {
-                                for (status, task) in status.iter().zip(&mut config.tasks) {
+                                for (status, &i) in status.iter().zip(&self.state.craft_order) {
+                                    let task = &mut config.tasks[i];
                                     if status.finished > 0 {
                                         log::debug!(
                                             "Marking {}x in '{}' as complete",
                                             status.finished,
                                             task.recipe.name
                                         );
-                                        task.quantity -= status.finished;
-                                        task.update_estimate(&self.state.macros);
+                                        task.finish_crafts(status.finished);
+                                        task.update_estimate(
+                                            &self.state.macros,
+                                            &self.state.timings,
+                                        );
                                     }
                                 }
                             }
                             config.tasks.retain(|t| t.quantity > 0);
                         }
 
+                        // A dry run doesn't add to the history.
+                        if !self.state.dry_run {
+                            self.load_history();
+                            for task in &mut config.tasks {
+                                task.update_estimate(&self.state.macros, &self.state.timings);
+                            }
+                        }
                         self.state.worker = WorkerStatus::Idle;
+                        self.state.dry_run = false;
                         self.state.craft_status = None;
+                        self.state.buffs.clear();
+                        self.state.session_end = None;
+                        self.state.session_crafts = None;
+                        self.state.craft_state = None;
+
+                        // The inventory is saved right away so it carries over
+                        // to the next run even if talan isn't closed cleanly.
+                        if self.state.config_changed {
+                            self.state.config_changed = false;
+                            if let Err(e) = write_config(Some(&self.config_path), config) {
+                                log::error!("Failed to save the configuration: {}", e);
+                            }
+                        }
                     }
                 }
             }
@@ -235,12 +452,361 @@ impl<'a, 'b> Gui<'a> {
             if self.state.show_gear_set_window {
                 self.gear_set_window(ui, config);
             }
+            if self.state.show_macro_diagnostics_window {
+                self.macro_diagnostics_window(ui);
+            }
+            if self.state.show_macro_export_window {
+                self.macro_export_window(ui, config);
+            }
+            if !self.state.macro_changes.is_empty() {
+                self.macro_changes_window(ui);
+            }
+            if self.state.show_order_window {
+                self.order_window(ui, config);
+            }
+            if self.state.show_inventory_window {
+                self.inventory_window(ui, config);
+            }
+            if self.state.show_stats_window {
+                self.stats_window(ui);
+            }
+            if self.state.show_session_window {
+                self.session_window(ui);
+            }
+            if self.state.dry_run_plan.is_some() {
+                self.dry_run_window(ui);
+            }
             // Always try to render a popup in case we have data primed for one.
             self.modal_popup_window(ui);
             self.progress_window(ui);
+            self.resume_window(ui, config);
         });
     }
 
+    /// Sends every task to the worker to craft, or to plan if |dry_run| is set.
+    fn start_craft(&mut self, config: &mut config::Config, dry_run: bool) {
+        // Wear on a gear set no job uses any more is forgotten, so it starts
+        // fresh if it's assigned again.
+        let gear = config.options.gear;
+        config
+            .options
+            .gearset_wear
+            .retain(|gearset, _| gear.contains(gearset));
+        let limits = match self.state.session.limits() {
+            Ok(limits) => limits,
+            Err(msg) => {
+                Gui::set_modal_text(&mut self.state, "Invalid session limits", &msg);
+                return;
+            }
+        };
+        // Get clippy to leave us alone about collapsing the if
+        if Gui::check_gear_sets(&mut self.state, config)
+            && Gui::check_job_levels(&mut self.state, config)
+            && Gui::check_macros(&mut self.state, config)
+        {
+            // Tasks are crafted in the planned order, leaving the list as the
+            // user has it.
+            let order: Vec<usize> = if config.options.optimize_task_order {
+                plan_order(&config.tasks)
+            } else {
+                (0..config.tasks.len()).collect()
+            };
+            let tasks = order.iter().map(|&i| config.tasks[i].clone()).collect();
+            self.state.craft_order = order;
+            self.send_to_worker(Request::Craft(Box::new(CraftRequest {
+                options: config.options.clone(),
+                tasks,
+                macros: self.state.macros.clone(),
+                dry_run,
+                checkpoint: if dry_run {
+                    None
+                } else {
+                    Some(checkpoint_path(&self.config_path))
+                },
+                inventory: if config.options.track_inventory {
+                    Some(config.inventory.clone())
+                } else {
+                    None
+                },
+                history: if dry_run {
+                    None
+                } else {
+                    Some(history_path(&self.config_path))
+                },
+                limits: limits.clone(),
+            })));
+            self.state.session_end = limits.end(Instant::now(), SystemTime::now());
+            self.state.session_crafts = limits.max_crafts;
+            // Set now rather than on the first status so a macro reload
+            // can't sneak in before the worker starts.
+            self.state.worker = WorkerStatus::Crafting;
+            self.state.dry_run = dry_run;
+        }
+    }
+
+    /// Puts the tasks in the order from the planner.
+    fn reorder_tasks(config: &mut config::Config) {
+        let order = plan_order(&config.tasks);
+        let mut tasks: Vec<Option<Task>> = config.tasks.drain(..).map(Some).collect();
+        config.tasks = order.into_iter().filter_map(|i| tasks[i].take()).collect();
+    }
+
+    /// Previews the order the planner would craft the tasks in.
+    fn order_window(&mut self, ui: &imgui::Ui, config: &mut config::Config) {
+        let mut apply = false;
+        Window::new(im_str!("Optimize Task Order"))
+            .size([500.0, 400.0], Condition::FirstUseEver)
+            .opened(&mut self.state.show_order_window)
+            .collapsible(false)
+            .build(ui, || {
+                let order = plan_order(&config.tasks);
+                ui.text(format!(
+                    "Gear set changes: {} now, {} optimized",
+                    job_changes(&config.tasks),
+                    job_changes(order.iter().map(|&i| &config.tasks[i]))
+                ));
+                ui.text_disabled("Pinned tasks keep their place in the list.");
+                ui.separator();
+                for (n, &i) in order.iter().enumerate() {
+                    let task = &config.tasks[i];
+                    let text = format!(
+                        "{}. [{}] {}x {}{}",
+                        n + 1,
+                        xiv::JOBS[task.recipe.job as usize],
+                        task.quantity,
+                        task.recipe.name,
+                        if task.pinned { " (pinned)" } else { "" }
+                    );
+                    if n == i {
+                        ui.text(text);
+                    } else {
+                        // Tasks that moved are highlighted.
+                        ui.text_colored([1.0, 1.0, 0.0, 1.0], text);
+                    }
+                }
+                ui.separator();
+                apply = ui.button(im_str!("Apply"), [0.0, 0.0]);
+            });
+        if apply {
+            Gui::reorder_tasks(config);
+            self.state.show_order_window = false;
+        }
+    }
+
+    /// Lists the materials on hand for crafts to be limited to.
+    fn inventory_window(&mut self, ui: &imgui::Ui, config: &mut config::Config) {
+        let mut import = false;
+        let mut removed = None;
+        let new_item_name = &mut self.state.new_item_name;
+        Window::new(im_str!("Inventory"))
+            .size([400.0, 400.0], Condition::FirstUseEver)
+            .opened(&mut self.state.show_inventory_window)
+            .collapsible(false)
+            .build(ui, || {
+                ui.checkbox(
+                    im_str!("Limit crafts to the inventory"),
+                    &mut config.options.track_inventory,
+                );
+                if ui.is_item_hovered() {
+                    ui.tooltip(|| {
+                        ui.text("Tasks stop when their materials run out, and crafts update the counts.")
+                    });
+                }
+                import = ui.button(im_str!("Import From Clipboard"), [0.0, 0.0]);
+                if ui.is_item_hovered() {
+                    ui.tooltip(|| ui.text("Lines of \"name,nq,hq\", or \"{NUM}x {NAME}\" for NQ."));
+                }
+                ui.separator();
+                for (i, (name, count)) in config.inventory.items.iter_mut().enumerate() {
+                    let id = ui.push_id(i as i32);
+                    ui.text(name);
+                    let _w = ui.push_item_width(100.0);
+                    let mut nq = count.nq as i32;
+                    if ui.input_int(im_str!("NQ"), &mut nq).build() {
+                        count.nq = max(nq, 0) as u32;
+                    }
+                    ui.same_line(0.0);
+                    let mut hq = count.hq as i32;
+                    if ui.input_int(im_str!("HQ"), &mut hq).build() {
+                        count.hq = max(hq, 0) as u32;
+                    }
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("delete")) {
+                        removed = Some(name.clone());
+                    }
+                    id.pop(ui);
+                }
+                ui.separator();
+                {
+                    let _w = ui.push_item_width(200.0);
+                    ui.input_text(im_str!("##new item"), new_item_name).build();
+                }
+                ui.same_line(0.0);
+                let name = new_item_name.to_str().trim().to_string();
+                if ui.button(im_str!("Add Item"), [0.0, 0.0]) && !name.is_empty() {
+                    config.inventory.items.entry(name).or_default();
+                    new_item_name.clear();
+                }
+            });
+        if let Some(name) = removed {
+            config.inventory.items.remove(&name);
+        }
+        if import {
+            match import_inventory_from_clipboard(&mut config.inventory) {
+                Ok(count) => log::info!("Imported {} inventory items", count),
+                Err(e) => {
+                    let msg = &format!("Couldn't read the clipboard: {}", e);
+                    Gui::set_modal_text(&mut self.state, "Import failed", msg);
+                }
+            }
+        }
+    }
+
+    /// Reads the craft history for the statistics window and task estimates.
+    fn load_history(&mut self) {
+        let path = history_path(&self.config_path);
+        let history = match read_history(&path) {
+            Ok(history) => history,
+            Err(e) => {
+                log::error!("Unable to read {}: {}", path.display(), e);
+                return;
+            }
+        };
+        self.state.stats = Stats::new(&history);
+        self.state.timings = Timings::new(&history);
+    }
+
+    /// Limits for the next craft, so it stops cleanly at a time or count.
+    fn session_window(&mut self, ui: &imgui::Ui) {
+        let session = &mut self.state.session;
+        Window::new(im_str!("Session Limits"))
+            .size(CONFIGURATION_SIZE, Condition::FirstUseEver)
+            .opened(&mut self.state.show_session_window)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(ui, || {
+                ui.text_disabled("Crafting stops before a synthesis that wouldn't fit.");
+                let _w = ui.push_item_width(100.0);
+                ui.checkbox(im_str!("Stop by"), &mut session.use_deadline);
+                ui.same_line(150.0);
+                ui.input_text(im_str!("(HH:MM)"), &mut session.deadline)
+                    .build();
+                ui.checkbox(im_str!("Stop after"), &mut session.use_duration);
+                ui.same_line(150.0);
+                if ui
+                    .input_int(im_str!("minutes"), &mut session.minutes)
+                    .build()
+                {
+                    session.minutes = max(session.minutes, 1);
+                }
+                ui.checkbox(im_str!("Stop after##crafts"), &mut session.use_crafts);
+                ui.same_line(150.0);
+                if ui.input_int(im_str!("crafts"), &mut session.crafts).build() {
+                    session.crafts = max(session.crafts, 1);
+                }
+            });
+    }
+
+    /// Totals from every craft in the history.
+    fn stats_window(&mut self, ui: &imgui::Ui) {
+        let stats = &self.state.stats;
+        Window::new(im_str!("Crafting Statistics"))
+            .size([500.0, 400.0], Condition::FirstUseEver)
+            .opened(&mut self.state.show_stats_window)
+            .collapsible(false)
+            .build(ui, || {
+                if stats.crafts == 0 {
+                    ui.text_disabled("Nothing has been crafted yet.");
+                    return;
+                }
+                let total = stats.total_ms().max(1);
+                let elapsed = |ms: u64| format_elapsed(Duration::from_millis(ms));
+                ui.text(format!(
+                    "{} crafts in {} ({:.1} per hour)",
+                    stats.crafts,
+                    elapsed(stats.total_ms()),
+                    stats.crafts_per_hour()
+                ));
+                ui.text(format!(
+                    "Actions: {} ({}%)",
+                    elapsed(stats.action_ms),
+                    stats.action_ms * 100 / total
+                ));
+                ui.text(format!(
+                    "Navigation: {} ({}%)",
+                    elapsed(stats.navigation_ms),
+                    stats.navigation_ms * 100 / total
+                ));
+                if stats.trials > 0 {
+                    ui.text_disabled(format!("{} trial syntheses not counted", stats.trials));
+                }
+                ui.separator();
+                ui.columns(3, im_str!("## Recipe Stats"), false /* no border */);
+                ui.text("Recipe");
+                ui.next_column();
+                ui.text("Crafts");
+                ui.next_column();
+                ui.text("Average time");
+                ui.next_column();
+                for (name, recipe) in &stats.recipes {
+                    ui.text(name);
+                    ui.next_column();
+                    ui.text(recipe.crafts.to_string());
+                    ui.next_column();
+                    ui.text(elapsed(recipe.average_ms()));
+                    ui.next_column();
+                }
+                ui.columns(1, im_str!("##"), false /* no border */);
+            });
+    }
+
+    /// Loads the macros from disk and re-selects the macro of every task in case
+    /// the macro file changed. Any task whose macro changed is reported.
+    fn load_macros(&mut self, config: &mut config::Config) {
+        self.state.macros.clear();
+        let mut included = Vec::new();
+        match read_macros_from_file(&self.macro_path, &mut self.state.macros, &mut included) {
+            Ok(diagnostics) => {
+                // Bring up the diagnostics so a bad macro doesn't go unnoticed.
+                self.state.show_macro_diagnostics_window = !diagnostics.is_empty();
+                self.state.macro_diagnostics = diagnostics;
+            }
+            Err(e) => {
+                log::error!("Failed to load macros: {}", e);
+                self.state.macro_diagnostics = vec![Diagnostic {
+                    severity: Severity::Error,
+                    macro_name: self.macro_path.display().to_string(),
+                    line: None,
+                    message: e.to_string(),
+                }];
+                self.state.show_macro_diagnostics_window = true;
+            }
+        }
+        included.insert(0, self.macro_path.clone());
+        self.state.macro_watcher.watch(included);
+
+        // Load saved tasks and re-map the macros in case the macro file changed.
+        self.state.macro_changes.clear();
+        for task in config.tasks.iter_mut() {
+            let previous = task.macro_name.clone();
+            task.select_macro(&self.state.macros, &config.options, &self.state.timings);
+            if task.macro_index(&self.state.macros).is_none() {
+                log::warn!(
+                    "Macro '{}' for {} no longer exists",
+                    task.macro_name,
+                    task.recipe.name
+                );
+            } else if !previous.is_empty() && previous != task.macro_name {
+                // Tasks saved before any macro was picked have nothing to compare.
+                self.state.macro_changes.push((
+                    task.recipe.name.clone(),
+                    previous,
+                    task.macro_name.clone(),
+                ));
+            }
+        }
+    }
+
     /// Stores the strings for the modal pop-up and sets it to appear on the next frame.
     fn set_modal_text(state: &mut UiState, title: &str, msg: &str) {
         state.modal_popup.title.clear();
@@ -273,6 +839,60 @@ impl<'a, 'b> Gui<'a> {
         }
     }
 
+    /// Offers to pick up where an interrupted craft left off.
+    fn resume_window(&mut self, ui: &imgui::Ui, config: &mut config::Config) {
+        let checkpoint = match &self.state.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => return,
+        };
+        let mut resume = None;
+        ui.open_popup(im_str!("Resume Crafting"));
+        ui.popup_modal(im_str!("Resume Crafting"))
+            .always_auto_resize(true)
+            .resizable(false)
+            .movable(false)
+            .build(|| {
+                ui.text(format!(
+                    "The last craft stopped unexpectedly after {} item(s){}.",
+                    checkpoint.finished_count(),
+                    checkpoint
+                        .job
+                        .map(|job| format!(" as {}", xiv::JOBS[job as usize]))
+                        .unwrap_or_default()
+                ));
+                ui.text("Resuming replaces the task list with what was left to craft:");
+                for task in checkpoint.remaining_tasks() {
+                    ui.bullet_text(&ImString::new(format!(
+                        "{}x {}",
+                        task.quantity, task.recipe.name
+                    )));
+                }
+                if ui.button(im_str!("Resume"), [0.0, 0.0]) {
+                    resume = Some(true);
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("Discard"), [0.0, 0.0]) {
+                    resume = Some(false);
+                }
+                if resume.is_some() {
+                    ui.close_current_popup();
+                }
+            });
+
+        if let Some(resume) = resume {
+            if resume {
+                config.tasks = checkpoint.remaining_tasks();
+                for task in &mut config.tasks {
+                    task.update_estimate(&self.state.macros, &self.state.timings);
+                }
+            }
+            if let Err(e) = remove_checkpoint(&checkpoint_path(&self.config_path)) {
+                log::error!("Failed to remove the checkpoint: {}", e);
+            }
+            self.state.checkpoint = None;
+        }
+    }
+
     fn main_menu(&mut self, ui: &imgui::Ui, config: &mut config::Config) {
         if let Some(main_menu) = ui.begin_main_menu_bar() {
             self.state.previous_window_size = ui.window_size();
@@ -280,6 +900,15 @@ impl<'a, 'b> Gui<'a> {
                 if MenuItem::new(im_str!("Reload Macros")).build(ui) {
                     self.state.should_load_macros = true;
                 }
+                if MenuItem::new(im_str!("Import Rotation From Clipboard")).build(ui) {
+                    self.import_rotations();
+                }
+                MenuItem::new(im_str!("Export Macro"))
+                    .build_with_ref(ui, &mut self.state.show_macro_export_window);
+                MenuItem::new(im_str!("Macro Diagnostics"))
+                    .build_with_ref(ui, &mut self.state.show_macro_diagnostics_window);
+                MenuItem::new(im_str!("Crafting Statistics"))
+                    .build_with_ref(ui, &mut self.state.show_stats_window);
                 if MenuItem::new(im_str!("Save All")).build(ui) {
                     match write_config(Some(&self.config_path), config) {
                         Ok(_) => log::info!("Wrote configuration to disk."),
@@ -292,14 +921,17 @@ impl<'a, 'b> Gui<'a> {
             }
             if let Some(menu) = ui.begin_menu(im_str!("Tasks"), true) {
                 if MenuItem::new(im_str!("Craft All")).build(ui) {
-                    // Get clippy to leave us alone about collapsing the if
-                    if Gui::check_gear_sets(&mut self.state, config) {
-                        self.send_to_worker(Request::Craft {
-                            options: config.options,
-                            tasks: config.tasks.clone(),
-                            macros: self.state.macros.clone(),
-                        });
-                    }
+                    self.start_craft(config, false);
+                }
+                MenuItem::new(im_str!("Optimize Order"))
+                    .build_with_ref(ui, &mut self.state.show_order_window);
+                MenuItem::new(im_str!("Session Limits"))
+                    .build_with_ref(ui, &mut self.state.show_session_window);
+                if MenuItem::new(im_str!("Dry Run")).build(ui) {
+                    self.start_craft(config, true);
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip(|| ui.text("Shows what Craft All would do, without the game."));
                 }
                 if MenuItem::new(im_str!("Import From Clipboard")).build(ui) {
                     if let Ok(items) = import_tasks_from_clipboard() {
@@ -322,6 +954,8 @@ impl<'a, 'b> Gui<'a> {
             if let Some(menu) = ui.begin_menu(im_str!("Options"), true) {
                 MenuItem::new(im_str!("Gear Configuration"))
                     .build_with_ref(ui, &mut self.state.show_gear_set_window);
+                MenuItem::new(im_str!("Inventory"))
+                    .build_with_ref(ui, &mut self.state.show_inventory_window);
                 ui.separator();
                 // For MenuItems if we use |build_with_ref| we can't have the menu ite,
                 // clear its own state.
@@ -338,11 +972,38 @@ impl<'a, 'b> Gui<'a> {
                 {
                     config.options.remove_finished_tasks = !config.options.remove_finished_tasks;
                 }
-                if MenuItem::new(im_str!("Use 'Trial Synthesis'"))
-                    .selected(config.options.use_trial_synthesis)
+                if MenuItem::new(im_str!("Optimize task order before crafting"))
+                    .selected(config.options.optimize_task_order)
                     .build(ui)
                 {
-                    config.options.use_trial_synthesis = !config.options.use_trial_synthesis;
+                    config.options.optimize_task_order = !config.options.optimize_task_order;
+                }
+                {
+                    let _w = ui.push_item_width(100.0);
+                    if ui
+                        .input_int(
+                            im_str!("Quick synthesis time per item (ms)"),
+                            &mut config.options.quick_synthesis_ms,
+                        )
+                        .build()
+                    {
+                        config.options.quick_synthesis_ms =
+                            max(config.options.quick_synthesis_ms, 0);
+                    }
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip(|| ui.text("Raise this if quick synthesis batches are cut short."));
+                }
+                if let Some(language_menu) = ui.begin_menu(im_str!("Client Language"), true) {
+                    for &language in Language::ALL.iter() {
+                        if MenuItem::new(&ImString::new(format!("{:?}", language)))
+                            .selected(config.options.language == language)
+                            .build(ui)
+                        {
+                            config.options.language = language;
+                        }
+                    }
+                    language_menu.end(ui);
                 }
                 menu.end(ui);
             }
@@ -365,21 +1026,100 @@ impl<'a, 'b> Gui<'a> {
                         // Progress bars look better with borders.
                         let token = ui.push_style_var(StyleVar::FrameBorderSize(1.0));
                         for s in status.iter() {
-                            let label =
-                                &ImString::new(format!("{} {}/{}", s.name, s.finished, s.total));
+                            let mut label = format!("{} {}/{}", s.name, s.finished, s.total);
+                            if s.collectables > 0 {
+                                label.push_str(&format!(" ({} collectable)", s.collectables));
+                            }
+                            if s.out_of_materials {
+                                label.push_str(" (out of materials)");
+                            }
+                            let label = &ImString::new(label);
                             ProgressBar::new(s.finished as f32 / s.total as f32)
                                 .overlay_text(label)
                                 .build(ui);
                         }
+                        let now = Instant::now();
+                        if let Some(end) = self.state.session_end {
+                            let left = end.saturating_duration_since(now).as_secs();
+                            ui.text(format!(
+                                "Session ends in {}:{:02}:{:02}",
+                                left / 3600,
+                                left / 60 % 60,
+                                left % 60
+                            ));
+                        }
+                        if let Some(crafts) = self.state.session_crafts {
+                            let finished: u32 = status.iter().map(|s| s.finished).sum();
+                            ui.text(format!("Session crafts: {} of {}", finished, crafts));
+                        }
+                        if let Some((state, since)) = self.state.craft_state {
+                            ui.text_disabled(format!(
+                                "{:?} for {}s",
+                                state,
+                                now.saturating_duration_since(since).as_secs()
+                            ));
+                        }
+                        for buff in &self.state.buffs {
+                            let left = buff.expires.saturating_duration_since(now).as_secs();
+                            if left > 0 {
+                                ui.text(format!(
+                                    "{}: {} ({}:{:02} left)",
+                                    buff.label,
+                                    buff.name,
+                                    left / 60,
+                                    left % 60
+                                ));
+                            } else {
+                                ui.text_colored(
+                                    [1.0, 0.5, 0.0, 1.0],
+                                    format!("{}: {} has worn off", buff.label, buff.name),
+                                );
+                            }
+                        }
                         ui.text(" ".repeat(100));
-                        if self.state.worker == WorkerStatus::Crafting {
-                            if ui.button(im_str!("Stop"), [0.0, 0.0]) {
-                                self.send_to_worker(Request::StopCrafting);
-                                // Ensure the worker thread stops crafting.
-                                self.state.worker = WorkerStatus::Stopping;
+                        match self.state.worker {
+                            WorkerStatus::Crafting => {
+                                if ui.button(im_str!("Pause"), [0.0, 0.0]) {
+                                    self.send_to_worker(Request::Pause);
+                                    self.state.worker = WorkerStatus::Pausing;
+                                }
+                                if ui.is_item_hovered() {
+                                    ui.tooltip(|| {
+                                        ui.text("Waits after the current synthesis, keeping the recipe selected.")
+                                    });
+                                }
+                                ui.same_line(0.0);
+                            }
+                            WorkerStatus::Pausing | WorkerStatus::Paused => {
+                                if ui.button(im_str!("Resume"), [0.0, 0.0]) {
+                                    self.send_to_worker(Request::Resume);
+                                    self.state.worker = WorkerStatus::Crafting;
+                                }
+                                ui.same_line(0.0);
+                            }
+                            _ => (),
+                        }
+                        match self.state.worker {
+                            WorkerStatus::Stopping | WorkerStatus::Idle => {
+                                ui.text("Waiting for any queued actions to finish")
+                            }
+                            _ => {
+                                if ui.button(im_str!("Stop"), [0.0, 0.0]) {
+                                    self.send_to_worker(Request::StopCrafting);
+                                    // Ensure the worker thread stops crafting.
+                                    self.state.worker = WorkerStatus::Stopping;
+                                }
                             }
-                        } else {
-                            ui.text("Waiting for any queued actions to finish");
+                        }
+                        match self.state.worker {
+                            WorkerStatus::Pausing => {
+                                ui.text_colored([1.0, 1.0, 0.0, 1.0], "Pausing after this synthesis")
+                            }
+                            WorkerStatus::Paused => ui.text_colored(
+                                [1.0, 1.0, 0.0, 1.0],
+                                "Paused. Leave the crafting log open to carry on where it stopped.",
+                            ),
+                            _ => (),
                         }
                         token.pop(ui);
                     }
@@ -469,9 +1209,15 @@ impl<'a, 'b> Gui<'a> {
                         ttc_min, ttc_sec
                     ));
                 }
+                // How much of each task the inventory covers, in list order.
+                let craftable = if config.options.track_inventory {
+                    config.inventory.plan(&config.tasks)
+                } else {
+                    Vec::new()
+                };
                 for (task_id, mut task) in &mut config.tasks.iter_mut().enumerate() {
                     let id = ui.push_id(task_id as i32);
-                    let header_name = ImString::new(format!(
+                    let mut header_name = format!(
                         "[{}] {}x {} (recipe lvl {} | {} durability | {} difficulty | {} quality)",
                         xiv::JOBS[task.recipe.job as usize],
                         task.quantity * task.recipe.result_amount,
@@ -480,7 +1226,12 @@ impl<'a, 'b> Gui<'a> {
                         task.recipe.durability,
                         task.recipe.difficulty,
                         task.recipe.quality,
-                    ));
+                    );
+                    if let Some(plan) = Gui::hq_plan_summary(task) {
+                        header_name.push_str(&format!(" [{}]", plan));
+                    }
+                    // The id keeps the header open while its text changes.
+                    let header_name = ImString::new(format!("{}###task", header_name));
                     if ui
                         .collapsing_header(&header_name)
                         .default_open(true)
@@ -496,7 +1247,15 @@ impl<'a, 'b> Gui<'a> {
                         let mut q: i32 = task.quantity as i32;
                         if ui.input_int(im_str!("#"), &mut q).build() {
                             task.quantity = max(1, q as u32);
-                            task.update_estimate(&self.state.macros);
+                            task.update_estimate(&self.state.macros, &self.state.timings);
+                        }
+                        if let Some(&count) = craftable.get(task_id) {
+                            let text = format!("Craftable: {} of {}", count, task.quantity);
+                            if count < task.quantity {
+                                ui.text_colored([1.0, 0.0, 0.0, 1.0], text);
+                            } else {
+                                ui.text_disabled(text);
+                            }
                         }
                         ui.next_column();
                         let m_labels: Vec<&ImStr> = self
@@ -505,21 +1264,162 @@ impl<'a, 'b> Gui<'a> {
                             .iter()
                             .map(|m| m.gui_name.as_ref())
                             .collect();
+                        let mut macro_id = task.macro_index(&self.state.macros).unwrap_or(0);
                         if ComboBox::new(im_str!("Macro")).build_simple_string(
                             ui,
-                            &mut task.macro_id,
+                            &mut macro_id,
                             &m_labels,
                         ) {
-                            task.update_estimate(&self.state.macros);
+                            // Picking a macro by hand pins it across reloads.
+                            task.macro_name = self.state.macros[macro_id].name.clone();
+                            task.macro_override = true;
+                            task.update_estimate(&self.state.macros, &self.state.timings);
+                        }
+                        if ui.is_item_hovered() {
+                            Gui::macro_candidates_tooltip(
+                                ui,
+                                &self.state.macros,
+                                &task.recipe,
+                                &config.options,
+                            );
+                        }
+                        if task.macro_override {
+                            ui.same_line(0.0);
+                            if ui.small_button(im_str!("auto")) {
+                                task.macro_override = false;
+                                task.select_macro(
+                                    &self.state.macros,
+                                    &config.options,
+                                    &self.state.timings,
+                                );
+                            }
+                        }
+                        if task.quick_synthesis {
+                            // Quick Synthesis doesn't need a macro.
+                        } else if task.macro_name.is_empty() {
+                            ui.text_colored(
+                                [1.0, 0.0, 0.0, 1.0],
+                                im_str!("No suitable macro, pick one to craft"),
+                            );
+                        } else {
+                            match task.macro_index(&self.state.macros) {
+                                None => ui.text_colored(
+                                    [1.0, 0.0, 0.0, 1.0],
+                                    &ImString::new(format!(
+                                        "Macro '{}' no longer exists",
+                                        task.macro_name
+                                    )),
+                                ),
+                                Some(i)
+                                    if task.is_collectable()
+                                        && !self.state.macros[i].ends_on_finish() =>
+                                {
+                                    ui.text_colored(
+                                        [1.0, 0.0, 0.0, 1.0],
+                                        im_str!("Collectables need a macro that ends the craft"),
+                                    )
+                                }
+                                Some(_) => (),
+                            }
                         }
                         ui.next_column();
-                        if !config.options.use_trial_synthesis {
+                        if task.can_quick_synthesize()
+                            && ui.checkbox(im_str!("Quick Synthesis"), &mut task.quick_synthesis)
+                        {
+                            task.trial_synthesis = false;
+                            task.update_estimate(&self.state.macros, &self.state.timings);
+                        }
+                        if ui.checkbox(im_str!("Trial Synthesis"), &mut task.trial_synthesis) {
+                            task.quick_synthesis = false;
+                            task.update_estimate(&self.state.macros, &self.state.timings);
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip(|| {
+                                ui.text("Runs the macro as many times as the quantity without using materials.")
+                            });
+                        }
+                        // The recipe's own flag can't be turned off.
+                        if task.recipe.collectable {
+                            ui.text_disabled(im_str!("Collectable"));
+                        } else if ui.checkbox(im_str!("Collectable"), &mut task.collectable)
+                            && task.collectable
+                        {
+                            task.quick_synthesis = false;
+                            task.update_estimate(&self.state.macros, &self.state.timings);
+                        }
+                        if task.is_collectable() {
+                            let _w = ui.push_item_width(100.0);
+                            if ui
+                                .input_int(
+                                    im_str!("Collectable delay (ms)"),
+                                    &mut task.collectable_delay,
+                                )
+                                .build()
+                            {
+                                task.collectable_delay = max(task.collectable_delay, 0);
+                            }
+                        }
+                        // Trial and quick syntheses don't pick materials.
+                        let picks_materials = !task.quick_synthesis && !task.trial_synthesis;
+                        if picks_materials {
                             ui.checkbox(im_str!("Specify Materials"), &mut task.specify_materials);
                         }
                         ui.next_column();
 
                         // Draw material widgets, or just the checkbox if checked.
-                        if task.specify_materials {
+                        if task.specify_materials && picks_materials {
+                            ui.text("HQ materials");
+                            ui.next_column();
+                            let spread_labels = [
+                                im_str!("Same for every craft"),
+                                im_str!("In the first crafts"),
+                                im_str!("Spread evenly"),
+                            ];
+                            let mut spread = HqSpread::ALL
+                                .iter()
+                                .position(|&s| s == task.hq_spread)
+                                .unwrap_or(0);
+                            if ComboBox::new(im_str!("##HQ spread")).build_simple_string(
+                                ui,
+                                &mut spread,
+                                &spread_labels,
+                            ) {
+                                task.hq_spread = HqSpread::ALL[spread];
+                                // Tasks from older configs have no stock yet.
+                                task.mat_stock
+                                    .resize(task.recipe.mats.len(), MaterialCount::default());
+                            }
+                            ui.next_column();
+                            ui.next_column();
+                        }
+                        if task.specify_materials
+                            && picks_materials
+                            && task.hq_spread != HqSpread::Fixed
+                        {
+                            // The stock on hand, which the crafts' HQ counts are worked out from.
+                            for (i, (mat, stock)) in task
+                                .recipe
+                                .mats
+                                .iter()
+                                .zip(task.mat_stock.iter_mut())
+                                .enumerate()
+                            {
+                                let id = ui.push_id(i as i32);
+                                ui.text(&ImString::new(mat.name.clone()));
+                                ui.next_column();
+                                let mut nq = stock.nq as i32;
+                                if ui.input_int(im_str!("NQ on hand"), &mut nq).build() {
+                                    stock.nq = max(0, nq) as u32;
+                                }
+                                ui.next_column();
+                                let mut hq = stock.hq as i32;
+                                if ui.input_int(im_str!("HQ on hand"), &mut hq).build() {
+                                    stock.hq = max(0, hq) as u32;
+                                }
+                                ui.next_column();
+                                id.pop(ui);
+                            }
+                        } else if task.specify_materials && picks_materials {
                             for (i, (mat, qual)) in task
                                 .recipe
                                 .mats
@@ -554,6 +1454,13 @@ impl<'a, 'b> Gui<'a> {
                         // Reset columns
                         ui.columns(1, im_str!("##"), false /* no border */);
 
+                        if task.specify_materials
+                            && picks_materials
+                            && task.hq_spread != HqSpread::Fixed
+                        {
+                            Gui::hq_plan_text(ui, task);
+                        }
+
                         // None of these task modifications can happen at the
                         // same time becaise it's not possible for a user to
                         // click multiple buttons in the same frame, at least I
@@ -576,14 +1483,77 @@ impl<'a, 'b> Gui<'a> {
                             self.state.task_list_modification =
                                 Some(TaskListModification::Delete(task_id));
                         }
+                        ui.same_line(0.0);
+                        ui.checkbox(im_str!("pinned"), &mut task.pinned);
+                        if ui.is_item_hovered() {
+                            ui.tooltip(|| {
+                                ui.text("Optimizing the task order won't move tasks past this one.")
+                            });
+                        }
                     }
                     id.pop(ui);
                 }
             });
     }
 
+    /// The HQ count |task|'s crafts use of material |mat|, as runs of crafts
+    /// using the same count.
+    fn hq_runs_text(task: &Task, mat: usize) -> String {
+        let runs: Vec<String> = task
+            .hq_runs(mat)
+            .iter()
+            .map(|(hq, crafts)| format!("{} HQ x{}", hq, crafts))
+            .collect();
+        runs.join(", ")
+    }
+
+    /// A one line summary of how |task| spreads its HQ materials, for the task's
+    /// row in the list, or None if it doesn't spread them.
+    fn hq_plan_summary(task: &Task) -> Option<String> {
+        let picks_materials = !task.quick_synthesis && !task.trial_synthesis;
+        if !task.specify_materials || !picks_materials || task.hq_spread == HqSpread::Fixed {
+            return None;
+        }
+        let mut summary: Vec<String> = task
+            .recipe
+            .mats
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| task.hq_runs(i).iter().any(|&(hq, _)| hq > 0))
+            .map(|(i, mat)| format!("{}: {}", mat.name, Gui::hq_runs_text(task, i)))
+            .collect();
+        if summary.is_empty() {
+            summary.push("no HQ".to_string());
+        }
+        let craftable = task.stock_craftable();
+        if craftable < task.quantity {
+            summary.push(format!("stock covers {}", craftable));
+        }
+        Some(summary.join("; "))
+    }
+
+    /// Shows the HQ count each of |task|'s crafts will use per material, and
+    /// any material there isn't enough of.
+    fn hq_plan_text(ui: &imgui::Ui, task: &Task) {
+        for (i, mat) in task.recipe.mats.iter().enumerate() {
+            ui.text_disabled(format!("{}: {}", mat.name, Gui::hq_runs_text(task, i)));
+            let stock = task.mat_stock.get(i).copied().unwrap_or_default();
+            let needed = mat.count * task.quantity;
+            if stock.nq + stock.hq < needed {
+                ui.same_line(0.0);
+                ui.text_colored(
+                    [1.0, 0.0, 0.0, 1.0],
+                    format!("({} short)", needed - stock.nq - stock.hq),
+                );
+            }
+        }
+    }
+
     /// The window for all configuration and optional settings.
     fn gear_set_window(&mut self, ui: &imgui::Ui, config: &mut config::Config) {
+        // Borrowed ahead of the window so the closure doesn't capture all of |self|.
+        let job_labels = &self.job_labels;
+        let profile_job = &mut self.state.profile_job;
         Window::new(im_str!("Gear Set Configuration"))
             .size(CONFIGURATION_SIZE, Condition::FirstUseEver)
             .opened(&mut self.state.show_gear_set_window)
@@ -619,6 +1589,276 @@ impl<'a, 'b> Gui<'a> {
                     ui.next_column();
                     id.pop(ui);
                 }
+                ui.columns(1, im_str!("##"), false);
+                ui.separator();
+
+                // Crafter stats are edited one job at a time to keep the window small.
+                let labels: Vec<&ImStr> =
+                    job_labels.iter().map(std::convert::AsRef::as_ref).collect();
+                ComboBox::new(im_str!("Crafter Profile")).build_simple_string(
+                    ui,
+                    profile_job,
+                    &labels,
+                );
+                let profile = &mut config.options.profiles[*profile_job];
+                if ui.input_int(im_str!("Level"), &mut profile.level).build() {
+                    profile.level = max(profile.level, 0);
+                }
+                if ui
+                    .input_int(im_str!("Craftsmanship"), &mut profile.craftsmanship)
+                    .build()
+                {
+                    profile.craftsmanship = max(profile.craftsmanship, 0);
+                }
+                if ui
+                    .input_int(im_str!("Control"), &mut profile.control)
+                    .build()
+                {
+                    profile.control = max(profile.control, 0);
+                }
+                if ui.input_int(im_str!("CP"), &mut profile.cp).build() {
+                    profile.cp = max(profile.cp, 0);
+                }
+                for (i, consumable) in
+                    [&mut profile.food, &mut profile.medicine, &mut profile.tea]
+                        .iter_mut()
+                        .enumerate()
+                {
+                    let id = ui.push_id(i as i32);
+                    let mut name = ImString::with_capacity(128);
+                    name.push_str(&consumable.name);
+                    let label = ImString::new(CONSUMABLE_LABELS[i]);
+                    if ui.input_text(&label, &mut name).build() {
+                        consumable.name = name.to_string();
+                    }
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("HQ"), &mut consumable.hq);
+                    ui.same_line(0.0);
+                    {
+                        let _w = ui.push_item_width(80.0);
+                        if ui
+                            .input_int(im_str!("Minutes"), &mut consumable.duration)
+                            .build()
+                        {
+                            consumable.duration = max(consumable.duration, 0);
+                        }
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip(|| {
+                            ui.text("Minutes the buff lasts. Leave at 0 to never use it while crafting.")
+                        });
+                    }
+                    id.pop(ui);
+                }
+                ui.separator();
+
+                // Crafts between gear upkeep, shared by every gear set.
+                let options = &mut config.options;
+                if ui
+                    .input_int(im_str!("Repair every N crafts"), &mut options.repair_interval)
+                    .build()
+                {
+                    options.repair_interval = max(options.repair_interval, 0);
+                }
+                if ui
+                    .input_int(
+                        im_str!("Extract materia every N crafts"),
+                        &mut options.extraction_interval,
+                    )
+                    .build()
+                {
+                    options.extraction_interval = max(options.extraction_interval, 0);
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip(|| {
+                        ui.text("Crafts it takes this gear to reach 100% spiritbond. 0 never extracts.")
+                    });
+                }
+                let gearset = options.gear[*profile_job];
+                if gearset == 0 {
+                    ui.text_disabled("No gear set to track wear for");
+                } else {
+                    let wear = options.gearset_wear.entry(gearset).or_default();
+                    ui.text(format!(
+                        "Gear set {}: {} crafts since repair, {} since extraction",
+                        gearset, wear.since_repair, wear.since_extraction
+                    ));
+                    ui.same_line(0.0);
+                    // For when the gear was looked after by hand.
+                    if ui.small_button(im_str!("Reset")) {
+                        *wear = GearWear::default();
+                    }
+                }
+            });
+    }
+
+    /// Imports Teamcraft or crafting optimizer rotations from the clipboard and
+    /// appends them to the macro file, reloading macros if that succeeds.
+    fn import_rotations(&mut self) {
+        let result = import_rotations_from_clipboard("Imported Rotation").and_then(|rotations| {
+            append_macros_to_file(&self.macro_path, &rotations)?;
+            Ok(rotations.len())
+        });
+        match result {
+            Ok(count) => {
+                let msg = format!(
+                    "Added {} rotation(s) to {}",
+                    count,
+                    self.macro_path.display()
+                );
+                Gui::set_modal_text(&mut self.state, "Rotations imported", &msg);
+                self.state.should_load_macros = true;
+            }
+            Err(e) => {
+                log::error!("Failed to import rotations: {}", e);
+                let msg = format!("Unable to import rotations: {}", e);
+                Gui::set_modal_text(&mut self.state, "Import failed", &msg);
+            }
+        }
+    }
+
+    /// Shows the plan from the last dry run, which can be copied as text.
+    fn dry_run_window(&mut self, ui: &imgui::Ui) {
+        let mut opened = true;
+        if let Some(plan) = &self.state.dry_run_plan {
+            Window::new(im_str!("Dry Run"))
+                .size([600.0, 500.0], Condition::FirstUseEver)
+                .opened(&mut opened)
+                .collapsible(false)
+                .build(ui, || {
+                    ui.text(format!("Total: {}", format_elapsed(plan.total)));
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("Copy to Clipboard")) {
+                        if let Err(e) = copy_to_clipboard(&plan.to_string()) {
+                            log::error!("Failed to copy the plan to the clipboard: {}", e);
+                        }
+                    }
+                    ui.separator();
+                    for entry in &plan.entries {
+                        ui.text_disabled(format_elapsed(entry.at));
+                        ui.same_line(80.0);
+                        ui.text(&entry.text);
+                    }
+                });
+        }
+        if !opened {
+            self.state.dry_run_plan = None;
+        }
+    }
+
+    /// Shows a macro as in-game macro pages that can be copied to the clipboard,
+    /// for when a craft needs to be done by hand.
+    fn macro_export_window(&mut self, ui: &imgui::Ui, config: &config::Config) {
+        // Split the borrow so the closure doesn't capture all of the state.
+        let UiState {
+            macros,
+            export_macro_id,
+            export_options,
+            show_macro_export_window,
+            ..
+        } = &mut self.state;
+        Window::new(im_str!("Export Macro"))
+            .size([500.0, 400.0], Condition::FirstUseEver)
+            .opened(show_macro_export_window)
+            .collapsible(false)
+            .build(ui, || {
+                if macros.is_empty() {
+                    ui.text_disabled("No macros are loaded.");
+                    return;
+                }
+                *export_macro_id = min(*export_macro_id, macros.len() - 1);
+                let m_labels: Vec<&ImStr> = macros.iter().map(|m| m.gui_name.as_ref()).collect();
+                ComboBox::new(im_str!("Macro")).build_simple_string(ui, export_macro_id, &m_labels);
+                ui.checkbox(im_str!("/macrolock"), &mut export_options.macrolock);
+                ui.same_line(0.0);
+                ui.checkbox(im_str!("Echo when done"), &mut export_options.echo);
+                ui.same_line(0.0);
+                ui.checkbox(
+                    im_str!("Sound between pages"),
+                    &mut export_options.sound_effects,
+                );
+                ui.separator();
+
+                export_options.language = config.options.language;
+                let pages = export_macro(&macros[*export_macro_id], export_options);
+                for (i, page) in pages.iter().enumerate() {
+                    let id = ui.push_id(i as i32);
+                    ui.text(format!("Page {}/{}", i + 1, pages.len()));
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("Copy")) {
+                        if let Err(e) = copy_to_clipboard(page) {
+                            log::error!("Failed to copy macro to the clipboard: {}", e);
+                        }
+                    }
+                    ui.text_disabled(page);
+                    ui.separator();
+                    id.pop(ui);
+                }
+            });
+    }
+
+    /// Lists every macro for the recipe, best match first, along with the rules
+    /// each of them passed or failed.
+    fn macro_candidates_tooltip(
+        ui: &imgui::Ui,
+        macros: &[Macro],
+        recipe: &Recipe,
+        options: &config::Options,
+    ) {
+        ui.tooltip(|| {
+            for c in rank_macros(macros, recipe, options) {
+                let name = ImString::new(format!("{} (score {})", macros[c.index].name, c.score));
+                if c.matches() {
+                    ui.text_colored([0.0, 1.0, 0.0, 1.0], &name);
+                } else {
+                    ui.text_disabled(&name);
+                }
+                for check in &c.checks {
+                    let mark = if check.passed { "+" } else { "-" };
+                    ui.text(&ImString::new(format!("    {} {}", mark, check.reason)));
+                }
+            }
+        });
+    }
+
+    /// Shows which tasks were given a different macro by the last reload.
+    fn macro_changes_window(&mut self, ui: &imgui::Ui) {
+        let changes = &mut self.state.macro_changes;
+        Window::new(im_str!("Macro Changes"))
+            .size([400.0, 0.0], Condition::FirstUseEver)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(ui, || {
+                ui.text("Reloading macros changed the macro of these tasks:");
+                for (task, previous, current) in changes.iter() {
+                    ui.text(task);
+                    ui.text_colored([0.9, 0.1, 0.1, 1.0], format!("- {}", previous));
+                    ui.text_colored([0.1, 0.6, 0.1, 1.0], format!("+ {}", current));
+                }
+                if ui.button(im_str!("Dismiss"), [0.0, 0.0]) {
+                    changes.clear();
+                }
+            });
+    }
+
+    /// Lists every problem found when the macro file was last loaded.
+    fn macro_diagnostics_window(&mut self, ui: &imgui::Ui) {
+        let diagnostics = &self.state.macro_diagnostics;
+        Window::new(im_str!("Macro Diagnostics"))
+            .size([600.0, 200.0], Condition::FirstUseEver)
+            .opened(&mut self.state.show_macro_diagnostics_window)
+            .collapsible(false)
+            .build(ui, || {
+                if diagnostics.is_empty() {
+                    ui.text_disabled("No problems found in the macro file.");
+                }
+                for d in diagnostics {
+                    let color = match d.severity {
+                        Severity::Warning => [0.9, 0.6, 0.0, 1.0],
+                        Severity::Error => [0.9, 0.1, 0.1, 1.0],
+                    };
+                    ui.text_colored(color, d.to_string());
+                }
             });
     }
 
@@ -636,4 +1876,53 @@ impl<'a, 'b> Gui<'a> {
         }
         true
     }
+
+    /// Ensures every task's recipe is within the level of its job's crafter profile.
+    fn check_job_levels(state: &mut UiState, config: &config::Config) -> bool {
+        for task in &config.tasks {
+            let profile = &config.options.profiles[task.recipe.job as usize];
+            if let Some(msg) = profile.level_warning(&task.recipe) {
+                Gui::set_modal_text(state, "Level too low", &msg);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Ensures every task still refers to a macro that exists.
+    fn check_macros(state: &mut UiState, config: &config::Config) -> bool {
+        for task in config.tasks.iter().filter(|t| !t.quick_synthesis) {
+            if task.macro_name.is_empty() {
+                let msg = format!(
+                    "No macro matches {}. Please pick a macro or add one for it.",
+                    task.recipe.name
+                );
+                Gui::set_modal_text(state, "No suitable macro", &msg);
+                return false;
+            }
+            match task.macro_index(&state.macros) {
+                None => {
+                    let msg = format!(
+                        "{} uses macro '{}', which no longer exists. Please pick another macro.",
+                        task.recipe.name, task.macro_name
+                    );
+                    Gui::set_modal_text(state, "Missing macro", &msg);
+                    return false;
+                }
+                // Anything sent after the craft ends would go to the collectable
+                // dialog instead of the chat box.
+                Some(i) if task.is_collectable() && !state.macros[i].ends_on_finish() => {
+                    let msg = format!(
+                        "{} is a collectable, but macro '{}' doesn't end on a synthesis action \
+                         or \"/talan finish\". Please pick another macro.",
+                        task.recipe.name, task.macro_name
+                    );
+                    Gui::set_modal_text(state, "Unfinished macro", &msg);
+                    return false;
+                }
+                Some(_) => (),
+            }
+        }
+        true
+    }
 }
@@ -0,0 +1,263 @@
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+// How a craft ended, as far as talan can tell without reading the game.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Outcome {
+    // Every action of the macro was sent.
+    Completed,
+    // Completed and accepted as a collectable.
+    Collected,
+    // Completed as a trial synthesis, so nothing was made.
+    Trial,
+    // Stopped partway through the macro.
+    Stopped,
+}
+
+// An action sent during a craft and how long it took until the next one, or
+// until the craft's last GCD ended.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActionTiming {
+    pub action: String,
+    pub ms: u64,
+}
+
+// A single craft, as appended to the history file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CraftRecord {
+    // Seconds since the Unix epoch when the craft finished.
+    pub timestamp: u64,
+    pub recipe_id: u32,
+    pub recipe_name: String,
+    // By index into xiv::JOBS.
+    pub job: u32,
+    pub macro_name: String,
+    pub actions: Vec<ActionTiming>,
+    // Everything since the previous craft finished, navigation included.
+    pub duration_ms: u64,
+    pub outcome: Outcome,
+}
+
+impl CraftRecord {
+    pub fn action_ms(&self) -> u64 {
+        self.actions.iter().map(|a| a.ms).sum()
+    }
+
+    // Time spent getting to the synthesis rather than on its actions.
+    pub fn navigation_ms(&self) -> u64 {
+        self.duration_ms.saturating_sub(self.action_ms())
+    }
+}
+
+// The history is kept next to the config it belongs to.
+pub fn history_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(HISTORY_FILE)
+}
+
+pub fn append_record(path: &Path, record: &CraftRecord) -> Result<(), Error> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+// Reads every record in the history. A missing file is an empty history, and
+// lines that can't be read, such as one cut short by a crash, are skipped.
+pub fn read_history(path: &Path) -> Result<Vec<CraftRecord>, Error> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                log::warn!("skipping history record: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+// Crafts and time spent on one recipe.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecipeStats {
+    pub crafts: u32,
+    pub total_ms: u64,
+}
+
+impl RecipeStats {
+    pub fn average_ms(&self) -> u64 {
+        self.total_ms / u64::from(self.crafts.max(1))
+    }
+}
+
+// Totals over the completed crafts of a history.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub crafts: u32,
+    pub action_ms: u64,
+    pub navigation_ms: u64,
+    // Trial syntheses completed, which aren't counted in anything else.
+    pub trials: u32,
+    // Keyed by recipe name.
+    pub recipes: BTreeMap<String, RecipeStats>,
+}
+
+impl Stats {
+    pub fn new(history: &[CraftRecord]) -> Self {
+        let mut stats = Stats::default();
+        for record in history.iter().filter(|r| r.outcome != Outcome::Stopped) {
+            if record.outcome == Outcome::Trial {
+                stats.trials += 1;
+                continue;
+            }
+            stats.crafts += 1;
+            stats.action_ms += record.action_ms();
+            stats.navigation_ms += record.navigation_ms();
+            let recipe = stats.recipes.entry(record.recipe_name.clone()).or_default();
+            recipe.crafts += 1;
+            recipe.total_ms += record.duration_ms;
+        }
+        stats
+    }
+
+    pub fn total_ms(&self) -> u64 {
+        self.action_ms + self.navigation_ms
+    }
+
+    pub fn crafts_per_hour(&self) -> f64 {
+        match self.total_ms() {
+            0 => 0.0,
+            ms => f64::from(self.crafts) * 3_600_000.0 / ms as f64,
+        }
+    }
+}
+
+// Average craft times from the history, for estimating tasks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timings {
+    // The whole craft, by recipe ID and macro name. Trial syntheses take as
+    // long as the real thing, so they're counted too.
+    crafts: HashMap<(u32, String), u64>,
+    // Navigation around any craft.
+    navigation_ms: Option<u64>,
+}
+
+impl Timings {
+    pub fn new(history: &[CraftRecord]) -> Self {
+        let mut crafts: HashMap<(u32, String), (u64, u64)> = HashMap::new();
+        let (mut navigation, mut count) = (0, 0);
+        for record in history.iter().filter(|r| r.outcome != Outcome::Stopped) {
+            let (total, n) = crafts
+                .entry((record.recipe_id, record.macro_name.clone()))
+                .or_default();
+            *total += record.duration_ms;
+            *n += 1;
+            navigation += record.navigation_ms();
+            count += 1;
+        }
+        Timings {
+            crafts: crafts
+                .into_iter()
+                .map(|(key, (total, n))| (key, total / n))
+                .collect(),
+            navigation_ms: navigation.checked_div(count),
+        }
+    }
+
+    // The average time a craft of |recipe_id| with |macro_name| has taken.
+    pub fn craft_ms(&self, recipe_id: u32, macro_name: &str) -> Option<u64> {
+        self.crafts
+            .get(&(recipe_id, macro_name.to_string()))
+            .copied()
+    }
+
+    // The average time crafts have spent outside their actions.
+    pub fn navigation_ms(&self) -> Option<u64> {
+        self.navigation_ms
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(recipe_id: u32, name: &str, action_ms: &[u64], duration_ms: u64) -> CraftRecord {
+        CraftRecord {
+            timestamp: 0,
+            recipe_id,
+            recipe_name: name.to_string(),
+            job: 0,
+            macro_name: "macro".to_string(),
+            actions: action_ms
+                .iter()
+                .map(|&ms| ActionTiming {
+                    action: "Basic Synthesis".to_string(),
+                    ms,
+                })
+                .collect(),
+            duration_ms,
+            outcome: Outcome::Completed,
+        }
+    }
+
+    #[test]
+    fn stats() {
+        let mut stopped = record(1, "Ingot", &[3000], 4000);
+        stopped.outcome = Outcome::Stopped;
+        let mut trial = record(1, "Ingot", &[3000, 3000], 9000);
+        trial.outcome = Outcome::Trial;
+        let history = vec![
+            record(1, "Ingot", &[3000, 3000], 10000),
+            record(1, "Ingot", &[3000, 3000], 8000),
+            record(2, "Axe", &[3000, 3000, 3000], 13000),
+            stopped,
+            trial,
+        ];
+
+        // Stopped crafts don't count towards anything, and trials only
+        // towards timings.
+        let stats = Stats::new(&history);
+        assert_eq!(stats.crafts, 3);
+        assert_eq!(stats.trials, 1);
+        assert_eq!(stats.action_ms, 21000);
+        assert_eq!(stats.navigation_ms, 10000);
+        assert_eq!(stats.recipes["Ingot"].average_ms(), 9000);
+        assert_eq!(stats.crafts_per_hour(), 3.0 * 3_600_000.0 / 31000.0);
+
+        let timings = Timings::new(&history);
+        assert_eq!(timings.craft_ms(1, "macro"), Some(9000));
+        assert_eq!(timings.craft_ms(1, "other"), None);
+        assert_eq!(timings.navigation_ms(), Some(13000 / 4));
+    }
+
+    #[test]
+    fn append() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("talan-history-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(read_history(&path)?.is_empty());
+
+        let first = record(1, "Ingot", &[3000], 5000);
+        let second = record(2, "Axe", &[2500, 3000], 9000);
+        append_record(&path, &first)?;
+        append_record(&path, &second)?;
+        // A record cut short doesn't lose the others.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"{\"timestamp\":")?;
+        assert_eq!(read_history(&path)?, vec![first, second]);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
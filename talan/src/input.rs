@@ -0,0 +1,302 @@
+use crate::craft::CraftError;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Keys sent by xiv::ui::clear_window, so a dry run can account for its time.
+const CLEAR_WINDOW_KEYS: u32 = 83;
+
+// The keys the crafter navigates the game with.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Forward,
+    Backward,
+    Confirm,
+    Cancel,
+    Enter,
+    Escape,
+    Backspace,
+    // Toggles the crafting log.
+    CraftingLog,
+}
+
+// Everything the crafter sends to the game goes through an Input, along with
+// its waits, so that a craft can be run without the game for a dry run.
+pub trait Input {
+    fn press(&mut self, key: Key);
+    fn send_string(&mut self, s: &str);
+    // Gets the game's UI back to a state we can trust.
+    fn clear_window(&mut self);
+    fn sleep(&mut self, duration: Duration);
+    fn now(&self) -> Instant;
+
+    // Wait |s| seconds, fractions permitted.
+    fn wait(&mut self, s: f32) {
+        self.sleep(Duration::from_millis((s * 1000_f32) as u64));
+    }
+
+    // Describes the input that follows, for the dry run's plan.
+    fn note(&mut self, _text: &str) {}
+
+    // Whether input is still reaching the game.
+    fn check(&self) -> Result<(), CraftError> {
+        Ok(())
+    }
+}
+
+// Sends input to the game window.
+pub struct GameInput {
+    handle: xiv::XivHandle,
+    // Input failures that happened before this input was created.
+    input_failures: usize,
+}
+
+impl GameInput {
+    pub fn new(handle: xiv::XivHandle) -> Self {
+        GameInput {
+            handle,
+            input_failures: xiv::ui::input_failures(),
+        }
+    }
+}
+
+impl Input for GameInput {
+    fn press(&mut self, key: Key) {
+        let handle = self.handle;
+        match key {
+            Key::Up => xiv::ui::cursor_up(handle),
+            Key::Down => xiv::ui::cursor_down(handle),
+            Key::Left => xiv::ui::cursor_left(handle),
+            Key::Right => xiv::ui::cursor_right(handle),
+            Key::Forward => xiv::ui::cursor_forward(handle),
+            Key::Backward => xiv::ui::cursor_backward(handle),
+            Key::Confirm => xiv::ui::press_confirm(handle),
+            Key::Cancel => xiv::ui::press_cancel(handle),
+            Key::Enter => xiv::ui::press_enter(handle),
+            Key::Escape => xiv::ui::press_escape(handle),
+            Key::Backspace => xiv::ui::press_backspace(handle),
+            Key::CraftingLog => xiv::ui::send_key(handle, 'N' as i32),
+        }
+    }
+
+    fn send_string(&mut self, s: &str) {
+        xiv::ui::send_string(self.handle, s);
+    }
+
+    fn clear_window(&mut self) {
+        xiv::ui::clear_window(self.handle);
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn check(&self) -> Result<(), CraftError> {
+        if !xiv::window_exists(self.handle) {
+            return Err(CraftError::WindowLost);
+        }
+        if xiv::ui::input_failures() != self.input_failures {
+            return Err(CraftError::InputFailed);
+        }
+        Ok(())
+    }
+}
+
+// A line of a dry run's plan, |at| into the craft.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanEntry {
+    pub at: Duration,
+    pub text: String,
+}
+
+// What a craft would do and when, as recorded by a dry run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Plan {
+    pub entries: Vec<PlanEntry>,
+    pub total: Duration,
+}
+
+// Formats |d| as minutes and seconds, e.g. 12:05.3.
+pub fn format_elapsed(d: Duration) -> String {
+    let tenths = d.as_millis() / 100;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "[{}] {}", format_elapsed(entry.at), entry.text)?;
+        }
+        write!(f, "Total: {}", format_elapsed(self.total))
+    }
+}
+
+// Records input instead of sending it. Time is kept on a simulated clock that
+// waits only advance, so a plan of hours takes moments to record.
+pub struct Recorder {
+    start: Instant,
+    elapsed: Duration,
+    entries: Vec<PlanEntry>,
+    // Consecutive key presses not yet in the plan, as (key, count), along
+    // with when the first of them was pressed.
+    keys: Vec<(Key, u32)>,
+    keys_at: Duration,
+    // Text in the chat box that hasn't been sent with Enter yet.
+    typed: Option<String>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder {
+            start: Instant::now(),
+            elapsed: Duration::default(),
+            entries: Vec::new(),
+            keys: Vec::new(),
+            keys_at: Duration::default(),
+            typed: None,
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn push(&mut self, at: Duration, text: String) {
+        self.entries.push(PlanEntry { at, text });
+    }
+
+    // Writes any pending key presses to the plan as one line.
+    fn flush_keys(&mut self) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let count: u32 = self.keys.iter().map(|(_, n)| n).sum();
+        let keys: Vec<String> = self
+            .keys
+            .drain(..)
+            .map(|(key, n)| match n {
+                1 => format!("{:?}", key),
+                n => format!("{:?} x{}", key, n),
+            })
+            .collect();
+        let text = format!("{} key(s): {}", count, keys.join(", "));
+        self.push(self.keys_at, text);
+    }
+
+    // Finishes recording, returning everything that was recorded.
+    pub fn finish(mut self) -> Plan {
+        self.flush_keys();
+        if let Some(typed) = self.typed.take() {
+            self.push(self.elapsed, format!("{} (never sent)", typed));
+        }
+        Plan {
+            entries: self.entries,
+            total: self.elapsed,
+        }
+    }
+}
+
+impl Input for Recorder {
+    fn press(&mut self, key: Key) {
+        match (key, self.typed.take()) {
+            // Enter sends what was typed, which is worth a line of its own.
+            (Key::Enter, Some(typed)) => {
+                self.flush_keys();
+                self.push(self.elapsed, typed);
+            }
+            (_, typed) => {
+                self.typed = typed;
+                match self.keys.last_mut() {
+                    Some((last, n)) if *last == key => *n += 1,
+                    Some(_) => self.keys.push((key, 1)),
+                    None => {
+                        self.keys_at = self.elapsed;
+                        self.keys.push((key, 1));
+                    }
+                }
+            }
+        }
+        self.wait(xiv::ui::UI_DELAY);
+    }
+
+    fn send_string(&mut self, s: &str) {
+        // An Enter right before typing only opens the chat box.
+        if self.typed.is_none() && self.keys.last() == Some(&(Key::Enter, 1)) {
+            self.keys.pop();
+        }
+        self.flush_keys();
+        self.typed.get_or_insert_with(String::new).push_str(s);
+        self.wait(xiv::ui::CHAR_DELAY * s.chars().count() as f32);
+    }
+
+    fn clear_window(&mut self) {
+        self.flush_keys();
+        self.push(self.elapsed, "clear the game window".to_string());
+        self.wait(xiv::ui::UI_DELAY * CLEAR_WINDOW_KEYS as f32);
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+
+    fn now(&self) -> Instant {
+        self.start + self.elapsed
+    }
+
+    fn note(&mut self, text: &str) {
+        self.flush_keys();
+        self.push(self.elapsed, format!("-- {}", text));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recorder() {
+        let mut recorder = Recorder::new();
+        recorder.note("select recipe");
+        recorder.press(Key::Backward);
+        recorder.press(Key::Backward);
+        recorder.press(Key::Confirm);
+        recorder.press(Key::Enter);
+        recorder.send_string("/ac Observe");
+        recorder.wait(1.5);
+        recorder.press(Key::Enter);
+        recorder.sleep(Duration::from_secs(60));
+
+        let plan = recorder.finish();
+        let found: Vec<(u128, &str)> = plan
+            .entries
+            .iter()
+            .map(|e| (e.at.as_millis(), e.text.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, "-- select recipe"),
+                (0, "3 key(s): Backward x2, Confirm"),
+                // Four keys and eleven characters before it's sent.
+                (2450, "/ac Observe"),
+            ]
+        );
+        assert_eq!(plan.total, Duration::from_millis(62550));
+        assert_eq!(
+            plan.to_string(),
+            "[0:00.0] -- select recipe\n\
+             [0:00.0] 3 key(s): Backward x2, Confirm\n\
+             [0:02.4] /ac Observe\n\
+             Total: 1:02.5"
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::lists::parse_item_list;
+use crate::task::{HqSpread, Task};
+use anyhow::{anyhow, Error, Result};
+use clipboard::{ClipboardContext, ClipboardProvider};
+use serde::{Deserialize, Serialize};
+use std::cmp::min;
+use std::collections::BTreeMap;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub struct ItemCount {
+    pub nq: u32,
+    pub hq: u32,
+}
+
+impl ItemCount {
+    pub fn total(&self) -> u32 {
+        self.nq + self.hq
+    }
+}
+
+// The materials the user has on hand, by item name. Items that aren't listed
+// are taken to be out of stock.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub struct Inventory {
+    #[serde(default)]
+    pub items: BTreeMap<String, ItemCount>,
+}
+
+impl Inventory {
+    fn count(&self, name: &str) -> ItemCount {
+        self.items.get(name).copied().unwrap_or_default()
+    }
+
+    // How many times |task| can be crafted from the inventory, ignoring its
+    // quantity. Tasks with a fixed material split need their exact NQ and HQ
+    // counts, otherwise either quality will do. Trial syntheses need nothing.
+    pub fn craftable(&self, task: &Task) -> u32 {
+        if task.trial_synthesis {
+            return u32::MAX;
+        }
+        let exact = task.specify_materials && task.hq_spread == HqSpread::Fixed;
+        let mut craftable = u32::MAX;
+        for (i, mat) in task.recipe.mats.iter().enumerate() {
+            let have = self.count(&mat.name);
+            let quality = task.mat_quality.get(i).copied().unwrap_or_default();
+            // Nothing needed of a quality doesn't limit anything.
+            let limits = if exact {
+                [
+                    have.nq.checked_div(quality.nq),
+                    have.hq.checked_div(quality.hq),
+                ]
+            } else {
+                [have.total().checked_div(mat.count), None]
+            };
+            for &limit in limits.iter().flatten() {
+                craftable = min(craftable, limit);
+            }
+        }
+        craftable
+    }
+
+    // Takes the materials for craft |craft| of |task|. Without specified
+    // materials HQ is used first, the same as the crafter picks them.
+    pub fn consume(&mut self, task: &Task, craft: u32) {
+        let materials = task.materials_for(craft);
+        for (i, mat) in task.recipe.mats.iter().enumerate() {
+            let have = self.items.entry(mat.name.clone()).or_default();
+            if task.specify_materials {
+                let quality = materials.get(i).copied().unwrap_or_default();
+                have.nq = have.nq.saturating_sub(quality.nq);
+                have.hq = have.hq.saturating_sub(quality.hq);
+            } else {
+                let hq = min(have.hq, mat.count);
+                have.hq -= hq;
+                have.nq = have.nq.saturating_sub(mat.count - hq);
+            }
+        }
+    }
+
+    // Adds what |count| crafts of |task| make. The quality isn't known ahead
+    // of time, so it's counted as NQ.
+    pub fn produce(&mut self, task: &Task, count: u32) {
+        let have = self.items.entry(task.recipe.name.clone()).or_default();
+        have.nq += count * task.recipe.result_amount;
+    }
+
+    // How many of each task can be crafted going through them in order, with
+    // earlier tasks using up materials and adding their results.
+    pub fn plan(&self, tasks: &[Task]) -> Vec<u32> {
+        let mut inventory = self.clone();
+        tasks
+            .iter()
+            .map(|task| {
+                let count = min(
+                    task.quantity,
+                    min(inventory.craftable(task), task.stock_craftable()),
+                );
+                if task.trial_synthesis {
+                    return count;
+                }
+                for craft in 0..count {
+                    inventory.consume(task, craft);
+                }
+                inventory.produce(task, count);
+                count
+            })
+            .collect()
+    }
+
+    // Sets the counts of every item in |text|, leaving other items alone.
+    pub fn import(&mut self, text: &str) -> usize {
+        let items = parse_inventory(text);
+        let count = items.len();
+        self.items.extend(items);
+        count
+    }
+}
+
+pub fn import_inventory_from_clipboard(inventory: &mut Inventory) -> Result<usize, Error> {
+    let mut ctx: ClipboardContext = ClipboardProvider::new().map_err(|e| anyhow!("{}", e))?;
+    let text = ctx.get_contents().map_err(|e| anyhow!("{}", e))?;
+    Ok(inventory.import(&text))
+}
+
+// Reads item counts from CSV or tab separated lines of "name,nq,hq", where
+// the HQ count is optional, or from "{NUM}x {NAME}" lists of NQ items. Lines
+// that don't have counts, such as a CSV header, are skipped.
+pub fn parse_inventory(text: &str) -> Vec<(String, ItemCount)> {
+    let mut items = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if !line.contains(',') && !line.contains('\t') {
+            for item in parse_item_list(line).unwrap_or_default() {
+                items.push((
+                    item.item,
+                    ItemCount {
+                        nq: item.count,
+                        hq: 0,
+                    },
+                ));
+            }
+            continue;
+        }
+        let fields: Vec<&str> = line
+            .split(&[',', '\t'][..])
+            .map(|f| f.trim().trim_matches('"'))
+            .collect();
+        let nq = fields.get(1).and_then(|f| f.parse().ok());
+        let hq = fields.get(2).map_or(Some(0), |f| f.parse().ok());
+        if let (Some(nq), Some(hq)) = (nq, hq) {
+            if !fields[0].is_empty() {
+                items.push((fields[0].to_string(), ItemCount { nq, hq }));
+            }
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task::{test_task, MaterialCount};
+
+    fn inventory(items: &[(&str, u32, u32)]) -> Inventory {
+        Inventory {
+            items: items
+                .iter()
+                .map(|&(name, nq, hq)| (name.to_string(), ItemCount { nq, hq }))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn craftable() {
+        let mut inv = inventory(&[("Ore", 5, 2), ("Sand", 3, 0)]);
+        let mut ingot = test_task("Ingot", 0, &[("Ore", 2), ("Sand", 1)], 10);
+        assert_eq!(inv.craftable(&ingot), 3);
+        // Both HQ ore are needed for each craft.
+        ingot.specify_materials = true;
+        ingot.mat_quality[0] = MaterialCount { nq: 0, hq: 2 };
+        assert_eq!(inv.craftable(&ingot), 1);
+
+        ingot.specify_materials = false;
+        for craft in 0..3 {
+            inv.consume(&ingot, craft);
+        }
+        // HQ goes first.
+        assert_eq!(inv.count("Ore"), ItemCount { nq: 1, hq: 0 });
+        assert_eq!(inv.craftable(&ingot), 0);
+    }
+
+    #[test]
+    fn plan() {
+        let inv = inventory(&[("Ore", 4, 0), ("Log", 1, 0)]);
+        let tasks = vec![
+            test_task("Ingot", 0, &[("Ore", 2)], 3),
+            test_task("Axe", 0, &[("Ingot", 1), ("Log", 1)], 2),
+        ];
+        // The axe can use the ingots made before it, but there's only one log.
+        assert_eq!(inv.plan(&tasks), vec![2, 1]);
+
+        // A task spreading HQ stops where the NQ it was given runs out.
+        let mut ingot = test_task("Ingot", 0, &[("Ore", 2)], 2);
+        ingot.specify_materials = true;
+        ingot.hq_spread = HqSpread::Even;
+        ingot.mat_stock[0] = MaterialCount { nq: 1, hq: 2 };
+        assert_eq!(inv.plan(&[ingot]), vec![1]);
+    }
+
+    #[test]
+    fn import() {
+        let mut inv = inventory(&[("Ore", 4, 0), ("Log", 1, 0)]);
+        let count = inv.import(
+            "Item,NQ,HQ\n\
+             \"Ore\",10,2\n\
+             Sand\t7\n\
+             3x Cloth\n\
+             not an item",
+        );
+        assert_eq!(count, 3);
+        assert_eq!(inv.count("Ore"), ItemCount { nq: 10, hq: 2 });
+        assert_eq!(inv.count("Sand"), ItemCount { nq: 7, hq: 0 });
+        assert_eq!(inv.count("Cloth"), ItemCount { nq: 3, hq: 0 });
+        assert_eq!(inv.count("Log"), ItemCount { nq: 1, hq: 0 });
+    }
+}
@@ -1,24 +1,56 @@
-use crate::action::{Action, ACTIONS};
+use crate::action::{find_action, Action, Language, ACTIONS};
+use crate::config::Options;
 use crate::recipe;
 use anyhow::{anyhow, Result};
+use clipboard::{ClipboardContext, ClipboardProvider};
 use imgui::ImString;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 // The |Toml| variant structures are used entirely for deserializing
 // from a user friendly format into the actions necessary for Talan.
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct MacroToml {
     pub name: String,
+    #[serde(default)]
     pub durability: Vec<u32>,
+    pub min_durability: Option<u32>,
+    pub max_durability: Option<u32>,
     pub max_rlvl: Option<u32>,
     pub min_rlvl: Option<u32>,
     pub difficulty: Option<u32>,
+    pub min_progress: Option<u32>,
+    pub max_progress: Option<u32>,
+    pub min_quality: Option<u32>,
+    pub max_quality: Option<u32>,
+    pub stars: Option<u32>,
+    pub expert: Option<bool>,
+    pub collectable: Option<bool>,
+    pub job: Option<String>,
     pub specialist: Option<bool>,
+    pub min_craftsmanship: Option<u32>,
+    pub min_control: Option<u32>,
+    pub min_cp: Option<u32>,
     pub actions: String,
 }
 
-#[derive(Debug, Deserialize)]
+// A named list of actions that macros can include with "@name".
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SequenceToml {
+    pub name: String,
+    pub actions: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct MacroFileToml {
+    // Other macro files whose sequences and macros can be included by name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequence: Vec<SequenceToml>,
+    #[serde(default)]
     pub xiv_macro: Vec<MacroToml>,
 }
 
@@ -27,166 +59,1141 @@ pub struct Macro {
     pub name: String,
     pub gui_name: ImString,
     pub durability: Vec<u32>,
+    pub min_durability: Option<u32>,
+    pub max_durability: Option<u32>,
     pub max_rlvl: Option<u32>,
     pub min_rlvl: Option<u32>,
     pub difficulty: Option<u32>,
+    pub min_progress: Option<u32>,
+    pub max_progress: Option<u32>,
+    pub min_quality: Option<u32>,
+    pub max_quality: Option<u32>,
+    pub stars: Option<u32>,
+    pub expert: Option<bool>,
+    pub collectable: Option<bool>,
+    // Index into xiv::JOBS.
+    pub job: Option<u32>,
     pub specialist: bool,
-    pub actions: Vec<&'static Action>,
+    pub min_craftsmanship: Option<u32>,
+    pub min_control: Option<u32>,
+    pub min_cp: Option<u32>,
+    pub steps: Vec<Step>,
 }
 
-// Attempts to parse macros in |buffer| and return a list of actions.
-fn parse_buffer(buffer: &str) -> Result<Vec<&'static Action>> {
-    let mut actions = vec![];
-    for line in buffer.trim().lines() {
-        if line.trim().as_bytes()[0] == b'#' {
-            log::trace!("skipping commented line: {}", line);
-            continue;
+impl Macro {
+    // The actions of the macro in order, leaving out directives.
+    pub fn actions(&self) -> impl Iterator<Item = &'static Action> + '_ {
+        self.steps.iter().filter_map(Step::action)
+    }
+
+    // Whether nothing is sent after the craft completes, which collectables
+    // need since the accept dialog takes any extra input. That's the case if
+    // the macro has a "/talan finish" or its last action adds progress.
+    pub fn ends_on_finish(&self) -> bool {
+        self.steps.contains(&Step::Finish) || self.actions().last().is_some_and(|a| a.progress)
+    }
+}
+
+// A single step of a parsed macro.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Step {
+    // Uses |action|. A <wait.N> on the line replaces the action's own wait.
+    Action {
+        action: &'static Action,
+        wait_ms: Option<u64>,
+    },
+    // Holds off the next step, from "/wait N" or "/talan pause N".
+    Pause(u64),
+    // "/talan finish" asserts the craft is complete at this point, so no
+    // steps after it are ever sent.
+    Finish,
+}
+
+impl Step {
+    pub fn action(&self) -> Option<&'static Action> {
+        match self {
+            Step::Action { action, .. } => Some(action),
+            _ => None,
         }
-        let action = parse_line(line.trim())?;
-        actions.push(action);
     }
 
-    Ok(actions)
+    // How long the step takes before the next one can be sent. An action
+    // without a <wait.N> uses its own wait.
+    pub fn duration_ms(&self) -> u64 {
+        match self {
+            Step::Action { action, wait_ms } => wait_ms.unwrap_or(action.wait_ms),
+            Step::Pause(ms) => *ms,
+            Step::Finish => 0,
+        }
+    }
 }
 
-pub fn read_macros_from_buffer(buffer: &str, out_vec: &mut Vec<Macro>) -> Result<()> {
-    let des = toml::from_str::<MacroFileToml>(buffer)?;
-    for macro_toml in &des.xiv_macro {
-        log::debug!("loaded '{}'", macro_toml.name);
-        out_vec.push(Macro {
-            name: macro_toml.name.clone(),
-            gui_name: ImString::new(macro_toml.name.clone()),
-            durability: macro_toml.durability.clone(),
-            max_rlvl: macro_toml.max_rlvl,
-            min_rlvl: macro_toml.min_rlvl,
-            difficulty: macro_toml.difficulty,
-            specialist: if let Some(spec) = macro_toml.specialist {
-                spec
+// Durabilities a recipe can have once its level table durability is scaled by
+// the recipe's durability factor.
+const KNOWN_DURABILITIES: [u32; 7] = [20, 30, 35, 40, 60, 70, 80];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    // The macro is loaded but likely won't work as intended.
+    Warning,
+    // The macro can never work and is not loaded.
+    Error,
+}
+
+// A problem found while loading a macro. |line| is the line within the macro
+// file, if the problem can be tied to one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub macro_name: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, macro_name: &str, line: Option<usize>, message: String) -> Self {
+        Diagnostic {
+            severity,
+            macro_name: macro_name.to_string(),
+            line,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}: [{}] line {}: {}",
+                severity, self.macro_name, line, self.message
+            ),
+            None => write!(f, "{}: [{}] {}", severity, self.macro_name, self.message),
+        }
+    }
+}
+
+// The actions of a [[sequence]] or macro that can be included by name, along
+// with the file it came from.
+struct Sequence {
+    origin: String,
+    actions: String,
+}
+
+// Every name that "@name" can refer to while loading a macro file.
+type Scope = HashMap<String, Sequence>;
+
+// Returns the lines of |buffer| that hold actions or includes along with their
+// line numbers, skipping blank lines and comments.
+fn action_lines(buffer: &str) -> impl Iterator<Item = (usize, &str)> {
+    buffer
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| {
+            if line.starts_with('#') {
+                log::trace!("skipping commented line: {}", line);
+            }
+            !line.is_empty() && !line.starts_with('#')
+        })
+}
+
+// Finds where the actions of each [[xiv_macro]] in |buffer| start, as the
+// number of lines before the first line of the actions string, so that lines
+// within the actions can be reported as lines of the file. Macros whose
+// actions can't be found are given 0.
+fn actions_line_offsets(buffer: &str) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut in_macro = false;
+    let mut in_string = false;
+    for (i, line) in buffer.lines().enumerate() {
+        let line = line.trim();
+        let quotes = line.matches("\"\"\"").count() + line.matches("'''").count();
+        if in_string {
+            in_string = quotes % 2 == 0;
+            continue;
+        }
+        if line.starts_with('[') {
+            in_macro = line == "[[xiv_macro]]";
+            if in_macro {
+                offsets.push(0);
+            }
+        } else if let Some((_, value)) = line
+            .split_once('=')
+            .filter(|(key, _)| in_macro && key.trim() == "actions")
+        {
+            let value = value.trim();
+            // A newline straight after the opening quotes isn't part of the string.
+            let first_line = if value == "\"\"\"" || value == "'''" {
+                i + 1
             } else {
-                false
+                i
+            };
+            if let Some(offset) = offsets.last_mut() {
+                *offset = first_line;
+            }
+        }
+        in_string = quotes % 2 == 1;
+    }
+    offsets
+}
+
+// Splits a trailing repetition such as "x3" off of |line|, returning the rest
+// of the line and the number of times to repeat it.
+fn split_repetition(line: &str) -> Result<(&str, usize)> {
+    if let Some(pos) = line.rfind(char::is_whitespace) {
+        let token = &line[pos + 1..];
+        if token.len() > 1
+            && token.starts_with('x')
+            && token[1..].chars().all(|c| c.is_ascii_digit())
+        {
+            let count = token[1..].parse::<usize>()?;
+            if count == 0 {
+                return Err(anyhow!("Repetition \"{}\" must be at least x1", token));
+            }
+            return Ok((line[..pos].trim_end(), count));
+        }
+    }
+    Ok((line, 1))
+}
+
+// Expands a single line of a macro into its actions. |stack| holds the names
+// currently being expanded so that an include of itself can be reported.
+fn expand_line(line: &str, scope: &Scope, stack: &mut Vec<String>) -> Result<Vec<Step>> {
+    let (line, count) = split_repetition(line)?;
+    let steps = match line.strip_prefix('@') {
+        Some(name) => {
+            let name = name.trim();
+            let sequence = scope
+                .get(name)
+                .ok_or_else(|| anyhow!("Unknown sequence \"@{}\"", name))?;
+            if stack.iter().any(|s| s == name) {
+                return Err(anyhow!("\"@{}\" includes itself", name));
+            }
+            stack.push(name.to_string());
+            let mut steps = Vec::new();
+            for (line_no, line) in action_lines(&sequence.actions) {
+                steps.extend(expand_line(line, scope, stack).map_err(|e| {
+                    anyhow!(
+                        "in \"@{}\" ({} line {}): {}",
+                        name,
+                        sequence.origin,
+                        line_no,
+                        e
+                    )
+                })?);
+            }
+            stack.pop();
+            steps
+        }
+        None => parse_step(line)?.into_iter().collect(),
+    };
+    Ok(steps.repeat(count))
+}
+
+// Attempts to parse macros in |buffer| and return a list of steps along with
+// the line each was found on. Included sequences and repetitions are expanded
+// in place, and their steps share the line of the include. Lines that can't
+// be parsed are reported in |diagnostics| and skipped so the rest of the macro
+// can still be checked.
+fn parse_buffer(
+    name: &str,
+    buffer: &str,
+    scope: &Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<(usize, Step)> {
+    let mut steps = vec![];
+    for (line_no, line) in action_lines(buffer) {
+        let mut stack = vec![name.to_string()];
+        match expand_line(line, scope, &mut stack) {
+            Ok(expanded) => steps.extend(expanded.into_iter().map(|step| (line_no, step))),
+            Err(e) => diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                name,
+                Some(line_no),
+                e.to_string(),
+            )),
+        }
+    }
+
+    steps
+}
+
+// Adds the sequences and macros of |file| to |scope|. Sequences win over
+// macros of the same name, and later files win over earlier ones.
+fn add_to_scope(file: &MacroFileToml, origin: &str, scope: &mut Scope) {
+    for m in &file.xiv_macro {
+        scope.insert(
+            m.name.clone(),
+            Sequence {
+                origin: origin.to_string(),
+                actions: m.actions.clone(),
+            },
+        );
+    }
+    for sequence in &file.sequence {
+        scope.insert(
+            sequence.name.clone(),
+            Sequence {
+                origin: origin.to_string(),
+                actions: sequence.actions.clone(),
             },
-            actions: parse_buffer(&macro_toml.actions)?,
-        });
+        );
     }
+}
 
-    Ok(())
+// Reads the macro file |include|, relative to |dir|, into |scope| along with
+// anything it includes itself. Every file read is added to |included|, which
+// also keeps a file from being read twice.
+fn read_include(
+    dir: Option<&Path>,
+    include: &str,
+    scope: &mut Scope,
+    included: &mut Vec<PathBuf>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let path = dir.map_or_else(|| PathBuf::from(include), |dir| dir.join(include));
+    if included.contains(&path) {
+        return;
+    }
+    included.push(path.clone());
+
+    let file = std::fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|buffer| Ok(toml::from_str::<MacroFileToml>(&buffer)?));
+    match file {
+        Ok(file) => {
+            for nested in &file.include {
+                read_include(path.parent(), nested, scope, included, diagnostics);
+            }
+            add_to_scope(&file, include, scope);
+        }
+        Err(e) => diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            include,
+            None,
+            format!("unable to include {}: {}", path.display(), e),
+        )),
+    }
 }
 
-pub fn read_macros_from_file(path: &std::path::Path, out_vec: &mut Vec<Macro>) -> Result<()> {
-    read_macros_from_buffer(&std::fs::read_to_string(path)?, out_vec)
+// Checks a parsed macro for problems that would stop it from working even
+// though every line is valid.
+fn validate_macro(
+    macro_toml: &MacroToml,
+    steps: &[(usize, Step)],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let name = &macro_toml.name;
+    if steps.iter().all(|(_, step)| step.action().is_none()) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            name,
+            None,
+            "macro has no actions".to_string(),
+        ));
+    }
+
+    // Macros without a durability are never picked for a recipe, which is
+    // how rotations imported without one arrive.
+    if macro_toml.durability.is_empty()
+        && macro_toml.min_durability.is_none()
+        && macro_toml.max_durability.is_none()
+    {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            name,
+            None,
+            "macro has no durability, so no recipe will use it".to_string(),
+        ));
+    } else if !KNOWN_DURABILITIES.iter().any(|&d| {
+        durability_matches(
+            &macro_toml.durability,
+            macro_toml.min_durability,
+            macro_toml.max_durability,
+            d,
+        )
+    }) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            name,
+            None,
+            format!(
+                "durability {:?} matches no recipe durability {:?}",
+                macro_toml.durability, KNOWN_DURABILITIES
+            ),
+        ));
+    }
+
+    // Nothing after a finish directive is ever sent.
+    let end = match steps.iter().position(|(_, step)| *step == Step::Finish) {
+        Some(pos) => {
+            if pos + 1 < steps.len() {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    name,
+                    Some(steps[pos + 1].0),
+                    format!(
+                        "{} step(s) after \"/talan finish\" on line {} are never used",
+                        steps.len() - pos - 1,
+                        steps[pos].0
+                    ),
+                ));
+            }
+            pos
+        }
+        None => steps.len(),
+    };
+    let actions: Vec<(usize, &'static Action)> = steps[..end]
+        .iter()
+        .filter_map(|(line, step)| step.action().map(|action| (*line, action)))
+        .collect();
+
+    // Without simulating the craft there's no telling which action finishes it,
+    // so the last progress action is assumed to. Anything after it is either sent
+    // to a finished craft, which breaks collectable windows, or means the craft
+    // can never finish.
+    match actions.iter().rposition(|(_, action)| action.progress) {
+        Some(pos) if pos + 1 < actions.len() => {
+            let (finish_line, finish) = actions[pos];
+            let (line, _) = actions[pos + 1];
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                name,
+                Some(line),
+                format!(
+                    "{} action(s) after \"{}\" on line {}, the last progress action, \
+                     which is assumed to finish the craft",
+                    actions.len() - pos - 1,
+                    finish.name,
+                    finish_line
+                ),
+            ));
+        }
+        None if !actions.is_empty() => diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            name,
+            None,
+            "macro has no action that can finish the craft".to_string(),
+        )),
+        _ => (),
+    }
 }
 
-// Extract the action for a given line in a macro. Returns a
-// String in the event of an error indicating a malformed macros.
-pub fn parse_line(line: &str) -> Result<&'static Action> {
-    let chars: Vec<char> = line.chars().collect();
-    if chars.len() < 4 || chars[0] != '/' || chars[1] != 'a' || chars[2] != 'c' || chars[3] != ' ' {
-        return Err(anyhow!("Macro is invalid: \"{}\"", line));
-    }
-
-    let mut has_quote = false;
-    let mut action_string = String::new();
-    let mut start = 4;
-    let mut pos = start;
-    while pos < chars.len() {
-        let c = chars[pos];
-        match c {
-            c if c.is_alphanumeric() || c.is_whitespace() || c == '\'' => action_string.push(c),
-            '\"' => {
-                if !has_quote {
-                    start += 1;
-                    has_quote = true;
-                } else {
-                    break;
+// Loads every macro in |buffer| into |out_vec|. Problems with individual macros
+// are returned as diagnostics, and macros with errors are left out rather than
+// failing the whole buffer. |path| is the file the buffer was read from, if
+// any. Includes are relative to its directory, or the working directory if
+// there is none, and every file included is added to |included|.
+fn read_macros(
+    buffer: &str,
+    path: Option<&Path>,
+    out_vec: &mut Vec<Macro>,
+    included: &mut Vec<PathBuf>,
+) -> Result<Vec<Diagnostic>> {
+    let des = toml::from_str::<MacroFileToml>(buffer)?;
+    let mut diagnostics = Vec::new();
+
+    // Included names are added first so that this file's names take precedence.
+    let mut scope = Scope::new();
+    let dir = path.and_then(Path::parent);
+    for include in &des.include {
+        read_include(dir, include, &mut scope, included, &mut diagnostics);
+    }
+    let origin = path.map_or_else(|| "buffer".to_string(), |p| p.display().to_string());
+    add_to_scope(&des, &origin, &mut scope);
+    let offsets = actions_line_offsets(buffer);
+
+    for (i, macro_toml) in des.xiv_macro.iter().enumerate() {
+        let mut macro_diagnostics = Vec::new();
+        // Tasks refer to macros by name, so names have to be unique.
+        if des.xiv_macro[..i].iter().any(|m| m.name == macro_toml.name) {
+            macro_diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                &macro_toml.name,
+                None,
+                "another macro already has this name".to_string(),
+            ));
+        }
+        let mut steps = parse_buffer(
+            &macro_toml.name,
+            &macro_toml.actions,
+            &scope,
+            &mut macro_diagnostics,
+        );
+        let offset = offsets.get(i).copied().unwrap_or(0);
+        for line in macro_diagnostics.iter_mut().filter_map(|d| d.line.as_mut()) {
+            *line += offset;
+        }
+        for (line, _) in &mut steps {
+            *line += offset;
+        }
+        validate_macro(macro_toml, &steps, &mut macro_diagnostics);
+        let job = match &macro_toml.job {
+            Some(job) => {
+                let pos = xiv::JOBS.iter().position(|j| j.eq_ignore_ascii_case(job));
+                if pos.is_none() {
+                    macro_diagnostics.push(Diagnostic::new(
+                        Severity::Error,
+                        &macro_toml.name,
+                        None,
+                        format!("unknown job \"{}\", expected one of {:?}", job, xiv::JOBS),
+                    ));
                 }
+                pos.map(|j| j as u32)
+            }
+            None => None,
+        };
+
+        for d in &macro_diagnostics {
+            match d.severity {
+                Severity::Warning => log::warn!("{}", d),
+                Severity::Error => log::error!("{}", d),
             }
-            '<' => break,
-            _ => return Err(anyhow!("Error at character '{}'", chars[pos])),
         }
-        pos += 1;
+        if macro_diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+        {
+            log::error!("skipping '{}'", macro_toml.name);
+        } else {
+            log::debug!("loaded '{}'", macro_toml.name);
+            out_vec.push(Macro {
+                name: macro_toml.name.clone(),
+                gui_name: ImString::new(macro_toml.name.clone()),
+                durability: macro_toml.durability.clone(),
+                min_durability: macro_toml.min_durability,
+                max_durability: macro_toml.max_durability,
+                max_rlvl: macro_toml.max_rlvl,
+                min_rlvl: macro_toml.min_rlvl,
+                difficulty: macro_toml.difficulty,
+                min_progress: macro_toml.min_progress,
+                max_progress: macro_toml.max_progress,
+                min_quality: macro_toml.min_quality,
+                max_quality: macro_toml.max_quality,
+                stars: macro_toml.stars,
+                expert: macro_toml.expert,
+                collectable: macro_toml.collectable,
+                job,
+                specialist: if let Some(spec) = macro_toml.specialist {
+                    spec
+                } else {
+                    false
+                },
+                min_craftsmanship: macro_toml.min_craftsmanship,
+                min_control: macro_toml.min_control,
+                min_cp: macro_toml.min_cp,
+                steps: steps.iter().map(|&(_, step)| step).collect(),
+            });
+        }
+        diagnostics.append(&mut macro_diagnostics);
     }
 
-    match ACTIONS.get(&*action_string.trim().to_lowercase()) {
-        Some(action) => Ok(action),
-        None => Err(anyhow!("Unknown action name \"{}\"", action_string)),
+    Ok(diagnostics)
+}
+
+pub fn read_macros_from_buffer(buffer: &str, out_vec: &mut Vec<Macro>) -> Result<Vec<Diagnostic>> {
+    read_macros(buffer, None, out_vec, &mut Vec::new())
+}
+
+// Loads the macros in the file at |path|. Every file it includes is added to
+// |included|, even if loading fails.
+pub fn read_macros_from_file(
+    path: &Path,
+    out_vec: &mut Vec<Macro>,
+    included: &mut Vec<PathBuf>,
+) -> Result<Vec<Diagnostic>> {
+    read_macros(
+        &std::fs::read_to_string(path)?,
+        Some(path),
+        out_vec,
+        included,
+    )
+}
+
+// Appends |macros| to the macro file at |path| in talan's TOML format, leaving
+// the existing contents and comments untouched. Tasks refer to macros by name,
+// so a macro whose name is already in the file gets a number after it.
+pub fn append_macros_to_file(path: &Path, macros: &[MacroToml]) -> Result<()> {
+    let mut names: Vec<String> = match std::fs::read_to_string(path) {
+        Ok(buffer) => toml::from_str::<MacroFileToml>(&buffer)?
+            .xiv_macro
+            .into_iter()
+            .map(|m| m.name)
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut macros = macros.to_vec();
+    for m in &mut macros {
+        m.name = unique_name(&m.name, &names);
+        names.push(m.name.clone());
     }
+
+    let buffer = toml::to_string(&MacroFileToml {
+        xiv_macro: macros,
+        ..Default::default()
+    })?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(b"\n")?;
+    file.write_all(buffer.as_bytes())?;
+    Ok(())
 }
 
-// Determine the best macro available for a given recipe. If no match is found,
-// return the first macro. Best match is determined by picking the macro who has
-// the most matching parameters specified in the macro definition.
-pub fn get_macro_for_recipe(macros: &[Macro], recipe: &recipe::Recipe, specialist: bool) -> usize {
-    let mut best_pick = None;
-    let mut best_match_cnt = 0;
-    log::trace!("Selecting macro for \"{}\"", recipe.name);
-    for (i, mcro) in macros.iter().enumerate() {
-        let mut match_cnt = 0;
-        if let Some(min_rlvl) = mcro.min_rlvl {
-            if min_rlvl > recipe.level {
-                log::trace!(
-                    "\t[{}] macro minimum level {} > recipe level {}",
-                    mcro.name,
-                    min_rlvl,
-                    recipe.level,
-                );
-                continue;
+// Returns |name|, or |name| followed by the lowest number from 2 up that isn't
+// in |names| if it is.
+fn unique_name(name: &str, names: &[String]) -> String {
+    if !names.iter().any(|n| n == name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|i| format!("{} {}", name, i))
+        .find(|candidate| !names.contains(candidate))
+        .unwrap()
+}
+
+// Formats |action| the way it would appear in an in-game macro.
+pub fn format_action(action: &'static Action) -> String {
+    format_step(
+        &Step::Action {
+            action,
+            wait_ms: None,
+        },
+        Language::English,
+    )
+    .unwrap_or_default()
+}
+
+// Formats |step| the way it would appear in an in-game macro on a client in
+// |language|. Directives the game doesn't understand give None. The game only
+// waits whole seconds, so action waits round up to leave the action time to
+// finish, while pauses round down, to no less than a second, so that a macro
+// exported again after being imported keeps its timing.
+pub fn format_step(step: &Step, language: Language) -> Option<String> {
+    match step {
+        Step::Action { action, .. } => Some(format!(
+            "/ac \"{}\" <wait.{}>",
+            action.name_in(language),
+            step.duration_ms().div_ceil(1000)
+        )),
+        Step::Pause(ms) => Some(format!("/wait {}", (ms / 1000).max(1))),
+        Step::Finish => None,
+    }
+}
+
+// The number of lines an in-game macro can hold.
+pub const MACRO_PAGE_LINES: usize = 15;
+
+// Optional extras added when exporting a macro for in-game use.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ExportOptions {
+    // Start every page with /macrolock.
+    pub macrolock: bool,
+    // End the last page with an echo announcing the macro is done.
+    pub echo: bool,
+    // Play a sound effect at the end of each page so it's clear when to
+    // start the next one.
+    pub sound_effects: bool,
+    // The client language actions are named in.
+    pub language: Language,
+}
+
+// Renders |m| as one or more in-game macros of at most MACRO_PAGE_LINES lines each.
+pub fn export_macro(m: &Macro, options: &ExportOptions) -> Vec<String> {
+    let header = options.macrolock as usize;
+    let mut pages = Vec::new();
+    let steps: Vec<String> = m
+        .steps
+        .iter()
+        .take_while(|step| **step != Step::Finish)
+        .filter_map(|step| format_step(step, options.language))
+        .collect();
+    let mut remaining = &steps[..];
+    loop {
+        let last_trailer = options.echo as usize;
+        let is_last = remaining.len() <= MACRO_PAGE_LINES - header - last_trailer;
+        let count = if is_last {
+            remaining.len()
+        } else {
+            MACRO_PAGE_LINES - header - options.sound_effects as usize
+        };
+
+        let mut lines = Vec::new();
+        if options.macrolock {
+            lines.push("/macrolock".to_string());
+        }
+        lines.extend_from_slice(&remaining[..count]);
+        let page = pages.len() + 1;
+        if is_last && options.echo {
+            if options.sound_effects {
+                lines.push(format!("/echo {} complete <se.{}>", m.name, page.min(16)));
+            } else {
+                lines.push(format!("/echo {} complete", m.name));
             }
-            match_cnt += 1;
+        } else if !is_last && options.sound_effects {
+            lines.push(format!(
+                "/echo Page {} complete <se.{}>",
+                page,
+                page.min(16)
+            ));
         }
+        pages.push(lines.join("\n"));
 
-        if let Some(max_rlvl) = mcro.max_rlvl {
-            if max_rlvl < recipe.level {
-                log::trace!(
-                    "\t[{}] macro maximum level {} < recipe level {}",
-                    mcro.name,
-                    max_rlvl,
-                    recipe.level,
-                );
-                continue;
-            }
-            match_cnt += 1;
+        remaining = &remaining[count..];
+        if is_last {
+            return pages;
         }
+    }
+}
+
+// Identifiers the crafting optimizer uses that don't match an action's name
+// once normalized. Combos expand to more than one action.
+const IMPORT_ALIASES: [(&str, &[&str]); 4] = [
+    ("basicsynth", &["basic synthesis"]),
+    ("mastersmend", &["master's mend"]),
+    ("focusedsynthesiscombo", &["observe", "focused synthesis"]),
+    ("focusedtouchcombo", &["observe", "focused touch"]),
+];
+
+// Lowercases |id| and strips everything that isn't a letter or digit, so that
+// "Byregot's Blessing", "ByregotsBlessing" and "byregotsBlessing" all match.
+fn normalize_action_id(id: &str) -> String {
+    id.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Maps an action identifier from a Teamcraft or crafting optimizer export
+// to the actions it represents.
+fn lookup_import_action(id: &str) -> Result<Vec<&'static Action>> {
+    let find = |normalized: &str| {
+        ACTIONS
+            .iter()
+            .find(|(key, _)| normalize_action_id(key) == normalized)
+            .map(|(_, action)| vec![action])
+            .or_else(|| {
+                IMPORT_ALIASES
+                    .iter()
+                    .find(|(alias, _)| *alias == normalized)
+                    .map(|(_, names)| names.iter().map(|n| &ACTIONS[n]).collect())
+            })
+    };
 
-        // Match on difficulty if it exists
-        if let Some(difficulty) = mcro.difficulty {
-            if difficulty != recipe.difficulty {
-                log::trace!("\t[{}] macro difficulty doesn't match", mcro.name,);
-                continue;
+    // The optimizer suffixes upgraded actions with a digit, e.g. "wasteNot2"
+    // for Waste Not II and "basicSynth2" for Basic Synthesis.
+    let normalized = normalize_action_id(id);
+    let base = normalized.trim_end_matches(|c: char| c.is_ascii_digit());
+    find(&normalized)
+        .or_else(|| {
+            if normalized.ends_with('2') {
+                find(&format!("{}ii", base))
+            } else {
+                None
             }
-            match_cnt += 1;
+        })
+        .or_else(|| find(base))
+        .ok_or_else(|| anyhow!("Unknown action \"{}\" in rotation", id))
+}
+
+// Reads a u32 from the first of |keys| present in |object|.
+fn json_u32(object: &serde_json::Value, keys: &[&str]) -> Option<u32> {
+    keys.iter()
+        .find_map(|key| object.get(key).and_then(serde_json::Value::as_u64))
+        .map(|v| v as u32)
+}
+
+// Converts a single JSON rotation into a macro. A rotation is either a plain
+// array of action identifiers, or an object holding that array under
+// "rotation" (Teamcraft) or "sequence" (crafting optimizer) along with an
+// optional "name" and "recipe". Talan's rlvl is the recipe's job level, so it
+// is read from the recipe's "lvl" or "baseLevel" rather than the level table
+// id Teamcraft calls "rlvl".
+fn import_json_rotation(value: &serde_json::Value, name: &str) -> Result<MacroToml> {
+    let steps = match value {
+        serde_json::Value::Array(_) => value,
+        _ => value
+            .get("rotation")
+            .or_else(|| value.get("sequence"))
+            .ok_or_else(|| anyhow!("Rotation has no \"rotation\" or \"sequence\" list"))?,
+    };
+    let steps = steps
+        .as_array()
+        .ok_or_else(|| anyhow!("Rotation steps are not a list"))?;
+
+    let mut actions = Vec::new();
+    for step in steps {
+        // Teamcraft's older exports wrap each step as { "action": "..." }.
+        let id = step
+            .as_str()
+            .or_else(|| step.get("action").and_then(serde_json::Value::as_str))
+            .ok_or_else(|| anyhow!("Unexpected rotation step: {}", step))?;
+        actions.extend(lookup_import_action(id)?);
+    }
+
+    let mut m = MacroToml {
+        name: value
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(name)
+            .to_string(),
+        actions: actions
+            .iter()
+            .map(|action| format_action(action) + "\n")
+            .collect(),
+        ..Default::default()
+    };
+    if let Some(recipe) = value.get("recipe") {
+        let level = json_u32(recipe, &["lvl", "baseLevel", "classJobLevel"]);
+        m.min_rlvl = level;
+        m.max_rlvl = level;
+        m.difficulty = json_u32(recipe, &["progress", "difficulty"]);
+        m.durability = json_u32(recipe, &["durability"]).into_iter().collect();
+    }
+    Ok(m)
+}
+
+// Converts the text of one or more in-game macros into a single macro.
+// Teamcraft splits long rotations into several 15 line macros separated by
+// /echo lines, so every /ac and /wait line is kept and the in-game only lines
+// dropped.
+fn import_text_rotation(buffer: &str, name: &str) -> Result<MacroToml> {
+    let mut actions = String::new();
+    for line in buffer
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if let Some(line) = parse_step(line)?
+            .as_ref()
+            .and_then(|step| format_step(step, Language::English))
+        {
+            actions.push_str(&line);
+            actions.push('\n');
+        }
+    }
+    if actions.is_empty() {
+        return Err(anyhow!("No actions found in rotation"));
+    }
+    Ok(MacroToml {
+        name: name.to_string(),
+        actions,
+        ..Default::default()
+    })
+}
+
+// Imports rotations exported from Teamcraft or the crafting optimizer. JSON
+// exports may hold a single rotation or a list of them, while text exports of
+// in-game macros become a single rotation. Rotations without a name of their
+// own are called |name|.
+pub fn import_rotations(buffer: &str, name: &str) -> Result<Vec<MacroToml>> {
+    let buffer = buffer.trim();
+    if !buffer.starts_with('[') && !buffer.starts_with('{') {
+        return Ok(vec![import_text_rotation(buffer, name)?]);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(buffer)?;
+    match &value {
+        // A list of strings is a single rotation, anything else is a list of rotations.
+        serde_json::Value::Array(list) if !list.iter().all(|v| v.is_string()) => list
+            .iter()
+            .enumerate()
+            .map(|(i, v)| import_json_rotation(v, &format!("{} {}", name, i + 1)))
+            .collect(),
+        _ => Ok(vec![import_json_rotation(&value, name)?]),
+    }
+}
+
+pub fn import_rotations_from_clipboard(name: &str) -> Result<Vec<MacroToml>> {
+    let mut ctx: ClipboardContext = ClipboardProvider::new().map_err(|e| anyhow!("{}", e))?;
+    import_rotations(&ctx.get_contents().map_err(|e| anyhow!("{}", e))?, name)
+}
+
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    let mut ctx: ClipboardContext = ClipboardProvider::new().map_err(|e| anyhow!("{}", e))?;
+    ctx.set_contents(text.to_string())
+        .map_err(|e| anyhow!("{}", e))
+}
+
+// Extract the action for a given line in a macro. Returns a
+// String in the event of an error indicating a malformed macros.
+pub fn parse_line(line: &str) -> Result<&'static Action> {
+    let rest = match line.strip_prefix("/ac ") {
+        Some(rest) => rest.trim_start(),
+        None => return Err(anyhow!("Not an /ac line: \"{}\"", line)),
+    };
+
+    // Quoted names end at the closing quote, while unquoted names run up to
+    // the first placeholder such as <wait.3>. Names are matched whole rather
+    // than character by character so any client language works.
+    let action_string = match rest.strip_prefix('"') {
+        Some(quoted) if quoted.contains('"') => quoted.split('"').next().unwrap_or_default(),
+        Some(quoted) => quoted.split('<').next().unwrap_or_default(),
+        None => rest.split('<').next().unwrap_or_default(),
+    };
+
+    match find_action(action_string) {
+        Some(action) => Ok(action),
+        None => Err(anyhow!("Unknown action name \"{}\"", action_string.trim())),
+    }
+}
+
+// In-game commands that don't affect a craft, and so are skipped.
+const SKIPPED_COMMANDS: [&str; 5] = ["/echo", "/e", "/macrolock", "/micon", "/macroicon"];
+
+// Converts a number of seconds, which may have a fraction, into milliseconds.
+fn parse_seconds(seconds: &str) -> Result<u64> {
+    match seconds.trim().parse::<f64>() {
+        Ok(s) if s.is_finite() && s >= 0.0 => Ok((s * 1000.0).round() as u64),
+        _ => Err(anyhow!("Invalid number of seconds \"{}\"", seconds.trim())),
+    }
+}
+
+// Extracts the wait of a <wait.N> placeholder on |line|, if it has one. Other
+// placeholders, such as <se.N>, are ignored.
+fn parse_wait(line: &str) -> Result<Option<u64>> {
+    match line.find("<wait.") {
+        Some(start) => {
+            let rest = &line[start + "<wait.".len()..];
+            let end = rest
+                .find('>')
+                .ok_or_else(|| anyhow!("Unterminated <wait.N> in \"{}\"", line))?;
+            Ok(Some(parse_seconds(&rest[..end])?))
         }
+        None => Ok(None),
+    }
+}
+
+// Parses one line of a macro into a step. In-game commands that don't matter
+// to talan, such as /echo and /macrolock, give None. Besides /ac and /wait,
+// talan has its own directives:
+//   /talan pause <seconds>   holds off the next step
+//   /talan finish            the craft is complete, send nothing after this
+pub fn parse_step(line: &str) -> Result<Option<Step>> {
+    let mut words = line.splitn(2, char::is_whitespace);
+    let command = words.next().unwrap_or_default();
+    let args = words.next().unwrap_or_default().trim();
+    if SKIPPED_COMMANDS.contains(&command) {
+        log::trace!("skipping in-game only line: {}", line);
+        return Ok(None);
+    }
 
-        if mcro.specialist {
-            if mcro.specialist != specialist {
-                log::trace!("\t[{}] macro speciality doesn't match", mcro.name,);
-                continue;
+    match command {
+        "/wait" => Ok(Some(Step::Pause(parse_seconds(args)?))),
+        "/talan" => {
+            let mut words = args.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("pause"), Some(seconds), None) => Ok(Some(Step::Pause(parse_seconds(seconds)?))),
+                (Some("finish"), None, None) => Ok(Some(Step::Finish)),
+                _ => Err(anyhow!(
+                    "Unknown directive \"{}\", expected \"/talan pause <seconds>\" or \"/talan finish\"",
+                    line
+                )),
             }
-            match_cnt += 1;
         }
+        _ => Ok(Some(Step::Action {
+            action: parse_line(line)?,
+            wait_ms: parse_wait(line)?,
+        })),
+    }
+}
 
-        if !mcro.durability.iter().any(|&d| d == recipe.durability) {
-            log::trace!("\t[{}] macro durability doesn't match", mcro.name);
-            continue;
+// Whether a recipe with |durability| is covered by a macro's durability list and
+// range. A macro has to give at least one of them to be selected.
+fn durability_matches(list: &[u32], min: Option<u32>, max: Option<u32>, durability: u32) -> bool {
+    if list.is_empty() && min.is_none() && max.is_none() {
+        return false;
+    }
+    (list.is_empty() || list.contains(&durability))
+        && min.unwrap_or(0) <= durability
+        && durability <= max.unwrap_or(u32::MAX)
+}
+
+// The outcome of one of a macro's match rules against a recipe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleCheck {
+    pub passed: bool,
+    pub reason: String,
+}
+
+// A macro considered for a recipe and why it did or didn't match.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    // Index into the macro list.
+    pub index: usize,
+    // Number of rules the macro specifies that the recipe passes. The more
+    // specific a macro is, the better it is assumed to fit.
+    pub score: u32,
+    pub checks: Vec<RuleCheck>,
+}
+
+impl Candidate {
+    pub fn matches(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+
+    fn check(&mut self, passed: bool, reason: String) {
+        if passed {
+            self.score += 1;
+        }
+        self.checks.push(RuleCheck { passed, reason });
+    }
+
+    fn check_min(&mut self, what: &str, min: Option<u32>, value: u32) {
+        if let Some(min) = min {
+            let passed = value >= min;
+            let op = if passed { ">=" } else { "<" };
+            self.check(passed, format!("{} {} {} minimum {}", what, value, op, min));
+        }
+    }
+
+    fn check_max(&mut self, what: &str, max: Option<u32>, value: u32) {
+        if let Some(max) = max {
+            let passed = value <= max;
+            let op = if passed { "<=" } else { ">" };
+            self.check(passed, format!("{} {} {} maximum {}", what, value, op, max));
+        }
+    }
+
+    fn check_flag(&mut self, what: &str, wanted: Option<bool>, value: bool) {
+        if let Some(wanted) = wanted {
+            let is = if value { "is" } else { "is not" };
+            self.check(wanted == value, format!("recipe {} {}", is, what));
         }
-        match_cnt += 1;
+    }
+}
 
-        if match_cnt > best_match_cnt {
-            best_match_cnt = match_cnt;
-            best_pick = Some(i);
+// Checks every rule of every macro against |recipe|. Matching macros come first,
+// best match first, followed by the macros that failed at least one rule. Ties
+// keep the order of the macro file. Stat minimums are checked against the
+// crafter profile of the recipe's job.
+pub fn rank_macros(macros: &[Macro], recipe: &recipe::Recipe, options: &Options) -> Vec<Candidate> {
+    let specialist = options.specialist[recipe.job as usize];
+    let profile = &options.profiles[recipe.job as usize];
+    let mut candidates: Vec<Candidate> = macros
+        .iter()
+        .enumerate()
+        .map(|(index, mcro)| {
+            let mut c = Candidate {
+                index,
+                score: 0,
+                checks: Vec::new(),
+            };
+            if let Some(job) = mcro.job {
+                c.check(
+                    job == recipe.job,
+                    format!(
+                        "recipe job {}, macro job {}",
+                        xiv::JOBS[recipe.job as usize],
+                        xiv::JOBS[job as usize]
+                    ),
+                );
+            }
+            c.check_min("recipe level", mcro.min_rlvl, recipe.level);
+            c.check_max("recipe level", mcro.max_rlvl, recipe.level);
+            if let Some(stars) = mcro.stars {
+                c.check(
+                    stars == recipe.stars,
+                    format!("recipe stars {}, macro stars {}", recipe.stars, stars),
+                );
+            }
+            if let Some(difficulty) = mcro.difficulty {
+                c.check(
+                    difficulty == recipe.difficulty,
+                    format!(
+                        "recipe difficulty {}, macro difficulty {}",
+                        recipe.difficulty, difficulty
+                    ),
+                );
+            }
+            c.check_min("progress", mcro.min_progress, recipe.difficulty);
+            c.check_max("progress", mcro.max_progress, recipe.difficulty);
+            c.check_min("quality", mcro.min_quality, recipe.quality);
+            c.check_max("quality", mcro.max_quality, recipe.quality);
+            c.check_flag("expert", mcro.expert, recipe.expert);
+            c.check_flag("collectable", mcro.collectable, recipe.collectable);
+            if mcro.specialist {
+                let is = if specialist { "is" } else { "is not" };
+                c.check(
+                    specialist,
+                    format!("{} {} a specialist", xiv::JOBS[recipe.job as usize], is),
+                );
+            }
+            c.check_min(
+                "craftsmanship",
+                mcro.min_craftsmanship,
+                profile.craftsmanship as u32,
+            );
+            c.check_min("control", mcro.min_control, profile.control as u32);
+            c.check_min("cp", mcro.min_cp, profile.cp as u32);
+
+            if !mcro.durability.is_empty() {
+                let passed = mcro.durability.contains(&recipe.durability);
+                let op = if passed { "in" } else { "not in" };
+                c.check(
+                    passed,
+                    format!(
+                        "durability {} {} {:?}",
+                        recipe.durability, op, mcro.durability
+                    ),
+                );
+            }
+            c.check_min("durability", mcro.min_durability, recipe.durability);
+            c.check_max("durability", mcro.max_durability, recipe.durability);
+            if mcro.durability.is_empty()
+                && mcro.min_durability.is_none()
+                && mcro.max_durability.is_none()
+            {
+                c.check(false, "macro has no durability".to_string());
+            }
+            c
+        })
+        .collect();
+    candidates.sort_by_key(|c| (!c.matches(), std::cmp::Reverse(c.score)));
+    candidates
+}
+
+// Determine the best macro available for a given recipe, or None if no macro
+// matches. Best match is determined by picking the macro who has the most
+// matching parameters specified in the macro definition.
+pub fn get_macro_for_recipe(
+    macros: &[Macro],
+    recipe: &recipe::Recipe,
+    options: &Options,
+) -> Option<usize> {
+    log::trace!("Selecting macro for \"{}\"", recipe.name);
+    let candidates = rank_macros(macros, recipe, options);
+    for c in &candidates {
+        log::trace!("\t[{}] score = {}", macros[c.index].name, c.score);
+        for check in c.checks.iter().filter(|check| !check.passed) {
+            log::trace!("\t\t{}", check.reason);
         }
-        log::trace!("\t[{}] score = {}", mcro.name, match_cnt);
     }
 
-    if best_pick.is_none() {
-        log::error!("No suitable macro found for \"{}\"", recipe.name);
+    match candidates.first() {
+        Some(c) if c.matches() => Some(c.index),
+        _ => {
+            log::error!("No suitable macro found for \"{}\"", recipe.name);
+            None
+        }
     }
-    best_pick.unwrap_or(0)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_line, MacroFileToml};
-    use crate::action::ACTIONS;
+    use super::{
+        parse_line, ExportOptions, Macro, MacroFileToml, Severity, Step, MACRO_PAGE_LINES,
+    };
+    use crate::action::{Language, ACTIONS};
+    use crate::config::Options;
     use crate::recipe::Recipe;
 
     const TEST_MACRO_TOML: &str = r#"
@@ -233,6 +1240,9 @@ mod tests {
             result_amount: 1,
             level: 0,
             specialist: false,
+            stars: 0,
+            expert: false,
+            collectable: false,
             id: 0,
             index: 0,
             job: 0,
@@ -248,12 +1258,131 @@ mod tests {
         recipe_70.durability = 70;
         recipe_80.durability = 80;
         let mut macros = Vec::new();
+        let options = Options::default();
+        assert!(super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).is_ok());
+        assert!(super::get_macro_for_recipe(&macros, &recipe_35, &options) == Some(0));
+        assert!(super::get_macro_for_recipe(&macros, &recipe_40, &options) == Some(2));
+        assert!(super::get_macro_for_recipe(&macros, &recipe_60, &options) == Some(0));
+        assert!(super::get_macro_for_recipe(&macros, &recipe_70, &options) == Some(2));
+        assert!(super::get_macro_for_recipe(&macros, &recipe_80, &options) == Some(1));
+    }
+
+    #[test]
+    fn crafter_stats() {
+        const MACRO_BUFFER: &str = r#"
+            [[xiv_macro]]
+            name = "any stats"
+            durability = [ 70 ]
+            actions = """"""
+
+            [[xiv_macro]]
+            name = "high stats"
+            durability = [ 70 ]
+            min_craftsmanship = 2500
+            min_control = 2400
+            min_cp = 550
+            actions = """"""
+        "#;
+
+        let recipe = Recipe {
+            durability: 70,
+            job: 3,
+            ..Default::default()
+        };
+        let mut macros = Vec::new();
+        let mut options = Options::default();
         assert!(super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).is_ok());
-        assert!(super::get_macro_for_recipe(&macros, &recipe_35, false) == 0);
-        assert!(super::get_macro_for_recipe(&macros, &recipe_40, false) == 2);
-        assert!(super::get_macro_for_recipe(&macros, &recipe_60, false) == 0);
-        assert!(super::get_macro_for_recipe(&macros, &recipe_70, false) == 2);
-        assert!(super::get_macro_for_recipe(&macros, &recipe_80, false) == 1);
+        assert_eq!(
+            super::get_macro_for_recipe(&macros, &recipe, &options),
+            Some(0)
+        );
+
+        options.profiles[3].craftsmanship = 2600;
+        options.profiles[3].control = 2400;
+        options.profiles[3].cp = 540;
+        assert_eq!(
+            super::get_macro_for_recipe(&macros, &recipe, &options),
+            Some(0)
+        );
+
+        options.profiles[3].cp = 560;
+        assert_eq!(
+            super::get_macro_for_recipe(&macros, &recipe, &options),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn match_keys() {
+        const MACRO_BUFFER: &str = r#"
+            [[xiv_macro]]
+            name = "expert"
+            durability = [ 70 ]
+            expert = true
+            actions = """"""
+
+            [[xiv_macro]]
+            name = "carpenter collectable"
+            job = "crp"
+            collectable = true
+            stars = 2
+            min_durability = 60
+            max_durability = 80
+            min_quality = 1000
+            max_progress = 5000
+            actions = """"""
+
+            [[xiv_macro]]
+            name = "fallback"
+            durability = [ 70 ]
+            actions = """"""
+
+            [[xiv_macro]]
+            name = "bad job"
+            durability = [ 70 ]
+            job = "MIN"
+            actions = """"""
+        "#;
+
+        let mut recipe = Recipe {
+            durability: 70,
+            difficulty: 4000,
+            quality: 2000,
+            stars: 2,
+            collectable: true,
+            ..Default::default()
+        };
+        let mut macros = Vec::new();
+        let options = Options::default();
+        let diagnostics = super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).unwrap();
+        assert_eq!(macros.len(), 3);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.macro_name == "bad job" && d.severity == Severity::Error));
+
+        let ranked = super::rank_macros(&macros, &recipe, &options);
+        let order: Vec<usize> = ranked.iter().map(|c| c.index).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+        assert_eq!(ranked[0].score, 7);
+        assert!(!ranked[2].matches());
+        assert_eq!(
+            ranked[2].checks[0],
+            super::RuleCheck {
+                passed: false,
+                reason: "recipe is not expert".to_string()
+            }
+        );
+
+        recipe.job = 1;
+        assert_eq!(
+            super::get_macro_for_recipe(&macros, &recipe, &options),
+            Some(2)
+        );
+        recipe.durability = 35;
+        assert_eq!(
+            super::get_macro_for_recipe(&macros, &recipe, &options),
+            None
+        );
     }
 
     #[test]
@@ -304,6 +1433,19 @@ mod tests {
         assert_eq!(entry.wait_ms, 2500);
     }
 
+    #[test]
+    fn macros_localized() {
+        // names from other client languages, including Japanese
+        let entry = parse_line(r#"/ac "ビエルゴの祝福" <wait.3>"#).unwrap();
+        assert_eq!(entry.name, "Byregot's Blessing");
+        let entry = parse_line(r#"/ac 作業 <wait.3>"#).unwrap();
+        assert_eq!(entry.name, "Basic Synthesis");
+        let entry = parse_line(r#"/ac "Bénédiction de Byregot" <wait.3>"#).unwrap();
+        assert_eq!(entry.name, "Byregot's Blessing");
+        let entry = parse_line(r#"/ac Große Schritte <wait.2>"#).unwrap();
+        assert_eq!(entry.name, "Great Strides");
+    }
+
     #[test]
     fn macros_empty() {
         let result = parse_line(r#""#);
@@ -385,10 +1527,653 @@ mod tests {
             &ACTIONS.get("waste not").unwrap(),
             &ACTIONS.get("waste not ii").unwrap(),
         ];
-        let actual = super::parse_buffer(TEST_MACRO_BUFFER)?;
-        for (&left, right) in expected.iter().zip(actual.iter()) {
-            assert_eq!(left, right);
+        let mut diagnostics = Vec::new();
+        let actual = super::parse_buffer(
+            "all actions",
+            TEST_MACRO_BUFFER,
+            &super::Scope::new(),
+            &mut diagnostics,
+        );
+        assert!(diagnostics.is_empty());
+        assert_eq!(actual.len(), expected.len());
+        for (&left, (_, right)) in expected.iter().zip(actual.iter()) {
+            assert_eq!(Some(*left), right.action());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn diagnostics() {
+        const MACRO_BUFFER: &str = r#"
+            [[xiv_macro]]
+            name = "bad action"
+            durability = [ 80 ]
+            actions = """
+                /ac Basic Touch
+                /ac Not An Action
+                /ac Basic Synthesis
+            """
+
+            [[xiv_macro]]
+            name = "not ac"
+            durability = [ 80 ]
+            actions = """
+                /gearset change 1
+                /ac Basic Synthesis
+            """
+
+            [[xiv_macro]]
+            name = "after finish"
+            durability = [ 45 ]
+            actions = """
+                /ac Basic Synthesis
+                /ac Basic Touch
+            """
+
+            [[xiv_macro]]
+            name = "empty"
+            durability = [ 80 ]
+            actions = """"""
+
+            [[xiv_macro]]
+            name = "no durability"
+            actions = """
+                /ac Basic Synthesis
+            """
+
+            [[xiv_macro]]
+            name = "fine"
+            durability = [ 35, 70 ]
+            actions = """
+                /ac Basic Touch
+
+                /ac Basic Synthesis
+            """
+
+            [[xiv_macro]]
+            name = "fine"
+            durability = [ 80 ]
+            actions = """
+                /ac Basic Synthesis
+            """
+        "#;
+
+        let mut macros = Vec::new();
+        let diagnostics = super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).unwrap();
+        let names: Vec<&str> = macros.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["after finish", "empty", "no durability", "fine"]
+        );
+
+        let found: Vec<(&str, Option<usize>, Severity)> = diagnostics
+            .iter()
+            .map(|d| (d.macro_name.as_str(), d.line, d.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("bad action", Some(7), Severity::Error),
+                ("not ac", Some(15), Severity::Error),
+                ("after finish", None, Severity::Warning),
+                ("after finish", Some(24), Severity::Warning),
+                ("empty", None, Severity::Warning),
+                ("no durability", None, Severity::Warning),
+                ("fine", None, Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_teamcraft_json() {
+        let rotations = super::import_rotations(
+            r#"["MuscleMemory", "Manipulation", "WasteNotII", "ByregotsBlessing", "Groundwork"]"#,
+            "Teamcraft",
+        )
+        .unwrap();
+        assert_eq!(rotations.len(), 1);
+        assert_eq!(rotations[0].name, "Teamcraft");
+        assert!(rotations[0].durability.is_empty());
+        assert_eq!(
+            rotations[0].actions,
+            "/ac \"Muscle Memory\" <wait.3>\n\
+             /ac \"Manipulation\" <wait.2>\n\
+             /ac \"Waste Not II\" <wait.2>\n\
+             /ac \"Byregot's Blessing\" <wait.3>\n\
+             /ac \"Groundwork\" <wait.3>\n"
+        );
+    }
+
+    #[test]
+    fn import_optimizer_json() {
+        const EXPORT: &str = r#"{
+            "name": "80 durability",
+            "recipe": { "baseLevel": 90, "level": 560, "difficulty": 3500, "durability": 80 },
+            "sequence": ["muscleMemory", "wasteNot2", "basicSynth2", "focusedTouchCombo", "mastersMend"]
+        }"#;
+        let rotations = super::import_rotations(EXPORT, "Optimizer").unwrap();
+        let m = &rotations[0];
+        assert_eq!(m.name, "80 durability");
+        assert_eq!(m.min_rlvl, Some(90));
+        assert_eq!(m.max_rlvl, Some(90));
+        assert_eq!(m.difficulty, Some(3500));
+        assert_eq!(m.durability, vec![80]);
+
+        let mut diagnostics = Vec::new();
+        let names: Vec<&str> =
+            super::parse_buffer(&m.name, &m.actions, &super::Scope::new(), &mut diagnostics)
+                .iter()
+                .filter_map(|(_, step)| step.action())
+                .map(|action| action.name)
+                .collect();
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            names,
+            vec![
+                "Muscle Memory",
+                "Waste Not II",
+                "Basic Synthesis",
+                "Observe",
+                "Focused Touch",
+                "Master's Mend"
+            ]
+        );
+    }
+
+    #[test]
+    fn import_multiple_rotations() {
+        const EXPORT: &str = r#"[
+            { "name": "first", "rotation": ["BasicSynthesis"], "recipe": { "lvl": 80, "durability": 70, "progress": 2000 } },
+            { "rotation": ["BasicTouch", "BasicSynthesis"] }
+        ]"#;
+        let rotations = super::import_rotations(EXPORT, "Imported").unwrap();
+        let names: Vec<&str> = rotations.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "Imported 2"]);
+        assert_eq!(rotations[0].difficulty, Some(2000));
+        assert_eq!(rotations[0].durability, vec![70]);
+        assert!(rotations[1].durability.is_empty());
+        assert!(super::import_rotations(r#"["NotAnAction"]"#, "Bad").is_err());
+    }
+
+    #[test]
+    fn import_text_macros() {
+        const EXPORT: &str = r#"
+            /macrolock
+            /ac "Muscle Memory" <wait.3>
+            /ac Manipulation <wait.2>
+            /echo Macro #1 complete <se.1>
+
+            /ac "Basic Synthesis" <wait.3>
+            /echo Macro #2 complete <se.2>
+        "#;
+        let rotations = super::import_rotations(EXPORT, "Text").unwrap();
+        assert_eq!(rotations.len(), 1);
+        assert_eq!(
+            rotations[0].actions,
+            "/ac \"Muscle Memory\" <wait.3>\n\
+             /ac \"Manipulation\" <wait.2>\n\
+             /ac \"Basic Synthesis\" <wait.3>\n"
+        );
+    }
+
+    // Imported macros must load back through the normal TOML path.
+    #[test]
+    fn import_round_trip() {
+        let mut rotations = super::import_rotations(
+            r#"{ "rotation": ["Groundwork"], "recipe": { "lvl": 90, "durability": 70 } }"#,
+            "Round Trip",
+        )
+        .unwrap();
+        rotations[0].specialist = Some(true);
+        let buffer = toml::to_string(&MacroFileToml {
+            xiv_macro: rotations,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut macros = Vec::new();
+        let diagnostics = super::read_macros_from_buffer(&buffer, &mut macros).unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(macros[0].name, "Round Trip");
+        assert_eq!(macros[0].durability, vec![70]);
+        assert_eq!(macros[0].min_rlvl, Some(90));
+        assert!(macros[0].specialist);
+        assert_eq!(macros[0].actions().next().unwrap().name, "Groundwork");
+    }
+
+    #[test]
+    fn append_unique_names() -> anyhow::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("talan-append-test-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let rotation = super::import_rotations(r#"["BasicSynthesis"]"#, "Imported")?;
+        super::append_macros_to_file(&path, &rotation)?;
+        super::append_macros_to_file(&path, &[rotation[0].clone(), rotation[0].clone()])?;
+        let buffer = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+
+        let names: Vec<String> = toml::from_str::<MacroFileToml>(&buffer)?
+            .xiv_macro
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(names, vec!["Imported", "Imported 2", "Imported 3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn export_pages() {
+        const MACRO_BUFFER: &str = r#"
+            [[xiv_macro]]
+            name = "Long"
+            durability = [ 70 ]
+            actions = """
+                /ac Muscle Memory
+                /ac Manipulation
+                /ac Veneration
+                /ac Waste Not II
+                /ac Groundwork
+                /ac Groundwork
+                /ac Innovation
+                /ac Preparatory Touch
+                /ac Preparatory Touch
+                /ac Preparatory Touch
+                /ac Preparatory Touch
+                /ac Great Strides
+                /ac Innovation
+                /ac Byregot's Blessing
+                /ac Careful Synthesis
+                /ac Careful Synthesis
+            """
+        "#;
+        let mut macros = Vec::new();
+        super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).unwrap();
+        let m = &macros[0];
+
+        let pages = super::export_macro(m, &ExportOptions::default());
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].lines().count(), MACRO_PAGE_LINES);
+        assert_eq!(pages[1], "/ac \"Careful Synthesis\" <wait.3>");
+
+        let options = ExportOptions {
+            macrolock: true,
+            echo: true,
+            sound_effects: true,
+            ..Default::default()
+        };
+        let pages = super::export_macro(m, &options);
+        assert_eq!(pages.len(), 2);
+        for page in &pages {
+            assert!(page.lines().count() <= MACRO_PAGE_LINES);
+            assert!(page.starts_with("/macrolock\n"));
         }
+        assert!(pages[0].ends_with("/echo Page 1 complete <se.1>"));
+        assert!(pages[1].ends_with("/echo Long complete <se.2>"));
+
+        // Every action line has to parse back to the same action.
+        let actions: Vec<&str> = pages
+            .iter()
+            .flat_map(|page| page.lines())
+            .filter(|line| line.starts_with("/ac"))
+            .map(|line| parse_line(line).unwrap().name)
+            .collect();
+        let expected: Vec<&str> = m.actions().map(|action| action.name).collect();
+        assert_eq!(actions, expected);
+
+        let rotations = super::import_rotations(&pages.join("\n"), "Long").unwrap();
+        let mut diagnostics = Vec::new();
+        let reimported = super::parse_buffer(
+            "Long",
+            &rotations[0].actions,
+            &super::Scope::new(),
+            &mut diagnostics,
+        );
+        assert_eq!(reimported.len(), m.steps.len());
+
+        // Actions are typed in the client's language, and still parse back.
+        let options = ExportOptions {
+            language: Language::German,
+            ..Default::default()
+        };
+        let pages = super::export_macro(m, &options);
+        assert!(pages[0].starts_with("/ac \"Motorisches Gedächtnis\" <wait.3>\n"));
+        let actions: Vec<&str> = pages
+            .iter()
+            .flat_map(|page| page.lines())
+            .map(|line| parse_line(line).unwrap().name)
+            .collect();
+        assert_eq!(actions, expected);
+    }
+
+    #[test]
+    fn directives_and_waits() {
+        const MACRO_BUFFER: &str = r#"
+            [[xiv_macro]]
+            name = "directives"
+            durability = [ 70 ]
+            actions = """
+                /macrolock
+                /ac Muscle Memory <wait.3>
+                /ac Veneration
+                /echo halfway <se.1>
+                /wait 2
+                /talan pause 1.5
+                /ac Groundwork <wait.3> <se.2>
+                /talan finish
+                /ac Basic Touch
+            """
+
+            [[xiv_macro]]
+            name = "bad directive"
+            durability = [ 70 ]
+            actions = """
+                /ac Groundwork
+                /talan stop
+            """
+        "#;
+
+        let mut macros = Vec::new();
+        let diagnostics = super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).unwrap();
+        assert_eq!(macros.len(), 1);
+        let found: Vec<(&str, Option<usize>, Severity)> = diagnostics
+            .iter()
+            .map(|d| (d.macro_name.as_str(), d.line, d.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("directives", Some(14), Severity::Warning),
+                ("bad directive", Some(22), Severity::Error),
+            ]
+        );
+
+        let m = &macros[0];
+        assert_eq!(
+            m.steps,
+            vec![
+                Step::Action {
+                    action: &ACTIONS["muscle memory"],
+                    wait_ms: Some(3000)
+                },
+                Step::Action {
+                    action: &ACTIONS["veneration"],
+                    wait_ms: None
+                },
+                Step::Pause(2000),
+                Step::Pause(1500),
+                Step::Action {
+                    action: &ACTIONS["groundwork"],
+                    wait_ms: Some(3000)
+                },
+                Step::Finish,
+                Step::Action {
+                    action: &ACTIONS["basic touch"],
+                    wait_ms: None
+                },
+            ]
+        );
+
+        // Nothing after the finish directive is exported.
+        let pages = super::export_macro(m, &ExportOptions::default());
+        assert_eq!(
+            pages,
+            vec![
+                "/ac \"Muscle Memory\" <wait.3>\n\
+                 /ac \"Veneration\" <wait.2>\n\
+                 /wait 2\n\
+                 /wait 1\n\
+                 /ac \"Groundwork\" <wait.3>"
+            ]
+        );
+    }
+
+    // Waits exported in whole seconds come back unchanged, and exporting an
+    // imported macro again gives the same pages.
+    #[test]
+    fn export_waits_round_trip() {
+        const MACRO_BUFFER: &str = r#"
+            [[xiv_macro]]
+            name = "waits"
+            durability = [ 70 ]
+            actions = """
+                /ac Muscle Memory <wait.4>
+                /wait 2
+                /talan pause 1.5
+                /talan pause 0.5
+                /ac Groundwork <wait.2.5>
+            """
+        "#;
+        let mut macros = Vec::new();
+        super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).unwrap();
+        let pages = super::export_macro(&macros[0], &ExportOptions::default());
+        assert_eq!(
+            pages,
+            vec![
+                "/ac \"Muscle Memory\" <wait.4>\n\
+                 /wait 2\n\
+                 /wait 1\n\
+                 /wait 1\n\
+                 /ac \"Groundwork\" <wait.3>"
+            ]
+        );
+
+        let rotations = super::import_rotations(&pages[0], "waits").unwrap();
+        let mut diagnostics = Vec::new();
+        let mut m = macros[0].clone();
+        m.steps = super::parse_buffer(
+            "waits",
+            &rotations[0].actions,
+            &super::Scope::new(),
+            &mut diagnostics,
+        )
+        .into_iter()
+        .map(|(_, step)| step)
+        .collect();
+        assert_eq!(
+            m.steps,
+            vec![
+                Step::Action {
+                    action: &ACTIONS["muscle memory"],
+                    wait_ms: Some(4000)
+                },
+                Step::Pause(2000),
+                Step::Pause(1000),
+                Step::Pause(1000),
+                Step::Action {
+                    action: &ACTIONS["groundwork"],
+                    wait_ms: Some(3000)
+                },
+            ]
+        );
+        assert_eq!(super::export_macro(&m, &ExportOptions::default()), pages);
+    }
+
+    #[test]
+    fn ends_on_finish() {
+        const MACRO_BUFFER: &str = r#"
+            [[xiv_macro]]
+            name = "progress last"
+            durability = [ 70 ]
+            actions = """
+                /ac Basic Touch
+                /ac Careful Synthesis
+            """
+
+            [[xiv_macro]]
+            name = "touch last"
+            durability = [ 70 ]
+            actions = """
+                /ac Careful Synthesis
+                /ac Basic Touch
+            """
+
+            [[xiv_macro]]
+            name = "directive"
+            durability = [ 70 ]
+            actions = """
+                /ac Careful Synthesis
+                /talan finish
+            """
+        "#;
+
+        let mut macros = Vec::new();
+        super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).unwrap();
+        let found: Vec<bool> = macros.iter().map(Macro::ends_on_finish).collect();
+        assert_eq!(found, vec![true, false, true]);
+    }
+
+    #[test]
+    fn sequences_and_repetition() {
+        const MACRO_BUFFER: &str = r#"
+            [[sequence]]
+            name = "opener"
+            actions = """
+                /ac Muscle Memory
+                /ac Manipulation
+            """
+
+            [[sequence]]
+            name = "quality"
+            actions = """
+                @opener
+                /ac Preparatory Touch x2
+            """
+
+            [[xiv_macro]]
+            name = "Uses sequences"
+            durability = [ 70 ]
+            actions = """
+                @quality
+                /ac "Basic Synthesis" <wait.3> x2
+            """
+
+            [[xiv_macro]]
+            name = "Uses a macro"
+            durability = [ 70 ]
+            actions = """
+                @Uses sequences x2
+            """
+
+            [[xiv_macro]]
+            name = "Unknown"
+            durability = [ 70 ]
+            actions = """
+                @closer
+            """
+
+            [[xiv_macro]]
+            name = "Recursive"
+            durability = [ 70 ]
+            actions = """
+                /ac Basic Touch
+                @Recursive
+            """
+        "#;
+
+        let mut macros = Vec::new();
+        let diagnostics = super::read_macros_from_buffer(MACRO_BUFFER, &mut macros).unwrap();
+        let names: Vec<&str> = macros[0].actions().map(|a| a.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Muscle Memory",
+                "Manipulation",
+                "Preparatory Touch",
+                "Preparatory Touch",
+                "Basic Synthesis",
+                "Basic Synthesis",
+            ]
+        );
+        assert_eq!(macros[1].steps.len(), 12);
+        assert_eq!(macros.len(), 2);
+
+        let errors: Vec<(&str, Option<usize>)> = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| (d.macro_name.as_str(), d.line))
+            .collect();
+        assert_eq!(errors, vec![("Unknown", Some(35)), ("Recursive", Some(43))]);
+        assert!(diagnostics[0].message.contains("@closer"));
+    }
+
+    #[test]
+    fn includes() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("talan-includes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("shared.toml"),
+            r#"
+            [[sequence]]
+            name = "opener"
+            actions = """
+                /ac Muscle Memory
+                /ac Veneration
+            """
+
+            [[sequence]]
+            name = "broken"
+            actions = """
+                /ac Veneration
+                /ac Not An Action
+            """
+            "#,
+        )?;
+        std::fs::write(
+            dir.join("macros.toml"),
+            r#"
+            include = [ "shared.toml", "missing.toml" ]
+
+            [[xiv_macro]]
+            name = "Included"
+            durability = [ 70 ]
+            actions = """
+                @opener
+                /ac Groundwork
+            """
+
+            [[xiv_macro]]
+            name = "Broken"
+            durability = [ 70 ]
+            actions = """
+                @broken
+            """
+
+            [[sequence]]
+            name = "local"
+            actions = "/ac Not An Action"
+
+            [[xiv_macro]]
+            name = "Local"
+            durability = [ 70 ]
+            actions = """
+                @local
+            """
+            "#,
+        )?;
+
+        let mut macros = Vec::new();
+        let mut included = Vec::new();
+        let diagnostics =
+            super::read_macros_from_file(&dir.join("macros.toml"), &mut macros, &mut included)?;
+        std::fs::remove_dir_all(&dir)?;
+
+        assert_eq!(
+            included,
+            vec![dir.join("shared.toml"), dir.join("missing.toml")]
+        );
+
+        assert_eq!(macros.len(), 1);
+        let names: Vec<&str> = macros[0].actions().map(|a| a.name).collect();
+        assert_eq!(names, vec!["Muscle Memory", "Veneration", "Groundwork"]);
+
+        assert_eq!(diagnostics[0].macro_name, "missing.toml");
+        assert_eq!(diagnostics[1].macro_name, "Broken");
+        assert!(diagnostics[1].message.contains("shared.toml line 2"));
+        let local = diagnostics
+            .iter()
+            .find(|d| d.macro_name == "Local")
+            .unwrap();
+        assert!(local.message.contains("macros.toml line 1"));
         Ok(())
     }
 }
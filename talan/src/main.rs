@@ -1,12 +1,19 @@
 mod action;
+mod checkpoint;
 mod config;
 mod craft;
 mod gui;
+mod history;
+mod input;
+mod inventory;
 mod lists;
 mod macros;
+mod planner;
 mod recipe;
 mod rpc;
+mod session;
 mod task;
+mod watch;
 
 use anyhow::{Error, Result};
 use rpc::{Request, Response, Worker};
@@ -0,0 +1,118 @@
+use crate::task::Task;
+
+// The job of the next pinned task from |i| on, which the tasks before it
+// are best finished with so there's no change going into it.
+fn pinned_job(tasks: &[Task], i: usize) -> Option<u32> {
+    tasks[i..].iter().find(|t| t.pinned).map(|t| t.recipe.job)
+}
+
+// Tasks that have to come before task |b|: any task making one of its
+// materials, and with pinned tasks in between, everything on the other side
+// of the pin.
+fn must_precede(tasks: &[Task], a: usize, b: usize) -> bool {
+    let makes_material = tasks[b]
+        .recipe
+        .mats
+        .iter()
+        .any(|mat| mat.name == tasks[a].recipe.name);
+    let pinned = a < b && (a..=b).any(|i| tasks[i].pinned);
+    a != b && (makes_material || pinned)
+}
+
+// Orders |tasks| to swap jobs as rarely as possible, returning the task
+// indices in their new order. Intermediates stay ahead of the tasks that use
+// them and nothing is moved past a pinned task, with the pinned task's job
+// saved for last ahead of it. Otherwise tasks keep their list order, so a
+// list that's already good isn't shuffled.
+pub fn plan_order(tasks: &[Task]) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..tasks.len()).collect();
+    let mut order = Vec::with_capacity(tasks.len());
+    let mut job = None;
+    while !remaining.is_empty() {
+        let ready: Vec<usize> = remaining
+            .iter()
+            .copied()
+            .filter(|&b| !remaining.iter().any(|&a| must_precede(tasks, a, b)))
+            .collect();
+        // Recipes can't really use each other, but if the names say otherwise
+        // the list order wins.
+        let next = ready
+            .iter()
+            .copied()
+            .find(|&i| Some(tasks[i].recipe.job) == job)
+            .or_else(|| {
+                ready
+                    .iter()
+                    .copied()
+                    .find(|&i| Some(tasks[i].recipe.job) != pinned_job(tasks, i))
+            })
+            .or_else(|| ready.first().copied())
+            .unwrap_or(remaining[0]);
+        job = Some(tasks[next].recipe.job);
+        remaining.retain(|&i| i != next);
+        order.push(next);
+    }
+    order
+}
+
+// How many times crafting |tasks| in order changes gear sets, counting the first.
+pub fn job_changes<'a>(tasks: impl IntoIterator<Item = &'a Task>) -> usize {
+    let mut job = None;
+    let mut changes = 0;
+    for task in tasks {
+        if job != Some(task.recipe.job) {
+            job = Some(task.recipe.job);
+            changes += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task::test_task;
+
+    #[test]
+    fn groups_jobs() {
+        let tasks = vec![
+            test_task("a", 0, &[], 1),
+            test_task("b", 1, &[], 1),
+            test_task("c", 0, &[], 1),
+            test_task("d", 1, &[], 1),
+        ];
+        let order = plan_order(&tasks);
+        assert_eq!(order, vec![0, 2, 1, 3]);
+        assert_eq!(job_changes(&tasks), 4);
+        assert_eq!(job_changes(order.iter().map(|&i| &tasks[i])), 2);
+    }
+
+    #[test]
+    fn intermediates_first() {
+        // The ingot is needed by the first task, on another job.
+        let tasks = vec![
+            test_task("Axe", 1, &[("Ingot", 1), ("Lumber", 1)], 1),
+            test_task("Lumber", 0, &[], 1),
+            test_task("Ingot", 1, &[], 1),
+            test_task("Stool", 0, &[("Lumber", 1)], 1),
+        ];
+        assert_eq!(plan_order(&tasks), vec![1, 3, 2, 0]);
+    }
+
+    #[test]
+    fn pins() {
+        let mut tasks = vec![
+            test_task("a", 0, &[], 1),
+            test_task("b", 1, &[], 1),
+            test_task("c", 0, &[], 1),
+            test_task("d", 1, &[], 1),
+            test_task("e", 0, &[], 1),
+        ];
+        tasks[2].pinned = true;
+        // Nothing crosses the pinned task. The tasks before it end on its
+        // job and the ones after start on it.
+        let order = plan_order(&tasks);
+        assert_eq!(order, vec![1, 0, 2, 4, 3]);
+        assert_eq!(job_changes(order.iter().map(|&i| &tasks[i])), 3);
+    }
+}
@@ -14,6 +14,12 @@ pub struct Recipe {
     pub result_amount: u32,
     pub level: u32,
     pub specialist: bool,
+    #[serde(default)]
+    pub stars: u32,
+    #[serde(default)]
+    pub expert: bool,
+    #[serde(default)]
+    pub collectable: bool,
     pub id: u32,
     pub index: usize,
     pub job: u32,
@@ -51,6 +57,9 @@ impl From<&xivapi::ApiRecipe> for Recipe {
             id: item.ID,
             name: item.Name.clone(),
             specialist: (item.IsSpecializationRequired == 1),
+            stars: item.RecipeLevelTable.Stars,
+            expert: (item.IsExpert == 1),
+            collectable: (item.ItemResult.IsCollectable == 1),
             job: item.CraftType.ID as u32,
             index: 0,
             mats,
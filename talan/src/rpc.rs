@@ -1,8 +1,14 @@
+use crate::checkpoint::{self, Checkpoint};
 use crate::config;
 use crate::craft;
+use crate::history;
+use crate::input::{GameInput, Input, Plan, Recorder};
+use crate::inventory::Inventory;
 use crate::macros::Macro;
 use crate::recipe;
+use crate::session::{SessionEnd, SessionLimits};
 use crate::task;
+use std::path::PathBuf;
 use std::sync::mpsc::{Receiver, Sender};
 
 #[derive(Debug)]
@@ -12,12 +18,29 @@ pub enum Request {
         job: Option<u32>,
         count: u32,
     },
-    Craft {
-        options: config::Options,
-        tasks: Vec<task::Task>,
-        macros: Vec<Macro>,
-    },
+    // Boxed because it's far bigger than any other request.
+    Craft(Box<CraftRequest>),
     StopCrafting,
+    // Wait between syntheses until resumed, keeping the craft's place.
+    Pause,
+    Resume,
+}
+
+#[derive(Debug)]
+pub struct CraftRequest {
+    pub options: config::Options,
+    pub tasks: Vec<task::Task>,
+    pub macros: Vec<Macro>,
+    // Record what the craft would do instead of sending it to the game.
+    pub dry_run: bool,
+    // Where to save progress after each item, if anywhere.
+    pub checkpoint: Option<PathBuf>,
+    // The materials on hand, if crafts should be limited to them.
+    pub inventory: Option<Inventory>,
+    // Where to append a record of every craft, if anywhere.
+    pub history: Option<PathBuf>,
+    // When to stop early, if at all.
+    pub limits: SessionLimits,
 }
 
 #[derive(Debug)]
@@ -27,6 +50,30 @@ pub enum Response {
         count: u32,
     },
     Craft(Vec<task::Status>),
+    // The consumable buffs applied so far this craft.
+    Buffs(Vec<craft::Buff>),
+    // What's left of the inventory after a craft.
+    Inventory(Inventory),
+    // Wear on gear set |gearset| changed.
+    Wear {
+        gearset: i32,
+        wear: config::GearWear,
+    },
+    // Crafting stopped early. |task| and |item| are where it stopped, if it
+    // got as far as crafting.
+    CraftError {
+        error: craft::CraftError,
+        task: Option<String>,
+        item: u32,
+    },
+    // The craft is waiting at a pause, or carrying on from one.
+    Paused(bool),
+    // Where the crafter has the game's UI now.
+    State(craft::CraftState),
+    // The craft stopped at a session limit, sent before its Eow.
+    SessionEnded(SessionEnd),
+    // The plan recorded by a dry run, sent before its Eow.
+    DryRun(Plan),
     Eow, // End of Work, aka finished.
 }
 
@@ -82,38 +129,127 @@ impl Worker {
                             count,
                         });
                     }
-                    Request::Craft {
-                        options,
-                        tasks,
-                        macros,
-                    } => {
+                    Request::Craft(request) => {
+                        let CraftRequest {
+                            options,
+                            tasks,
+                            macros,
+                            dry_run,
+                            checkpoint,
+                            inventory,
+                            history,
+                            limits,
+                        } = *request;
                         // Send a full status update to the main thread after completing
                         // an item.
                         let status_fn = |status: &[task::Status]| {
+                            if let Some(path) = &checkpoint {
+                                if status.iter().any(|s| s.finished > 0) {
+                                    let progress = Checkpoint::new(&tasks, status);
+                                    if let Err(e) = checkpoint::write_checkpoint(path, &progress) {
+                                        log::error!("failed to write checkpoint: {}", e);
+                                    }
+                                }
+                            }
                             self.reply(Response::Craft(status.to_vec()));
                         };
 
-                        // Check whether crafting should continue after each craft.
-                        let continue_fn = || -> bool {
-                            if let Some(Request::StopCrafting) = self.try_receive() {
-                                    return false;
+                        let event_fn = |event: craft::Event| {
+                            // Nothing happened to the character in a dry run.
+                            if dry_run {
+                                return;
                             }
-                            true
+                            let response = match event {
+                                // The record goes straight to the history file,
+                                // which the client reads when it needs to.
+                                craft::Event::Craft(record) => {
+                                    if let Some(path) = &history {
+                                        if let Err(e) = history::append_record(path, &record) {
+                                            log::error!("failed to record craft: {}", e);
+                                        }
+                                    }
+                                    return;
+                                }
+                                craft::Event::Paused(paused) => Response::Paused(paused),
+                                craft::Event::State(state) => Response::State(state),
+                                craft::Event::SessionEnded(reason) => {
+                                    Response::SessionEnded(reason)
+                                }
+                                craft::Event::Buffs(buffs) => Response::Buffs(buffs),
+                                craft::Event::Inventory(inventory) => {
+                                    Response::Inventory(inventory)
+                                }
+                                craft::Event::Wear { gearset, wear } => {
+                                    Response::Wear { gearset, wear }
+                                }
+                            };
+                            self.reply(response);
                         };
 
-                        // If init throws an error we'll have a log to console anyway.
-                        if let Ok(handle) = xiv::init() {
-                            let craft = craft::Crafter::new(
-                                handle,
+                        // Check whether crafting should continue after each action.
+                        let continue_fn = || match self.try_receive() {
+                            Some(Request::StopCrafting) => craft::Control::Stop,
+                            Some(Request::Pause) => craft::Control::Pause,
+                            Some(Request::Resume) => craft::Control::Resume,
+                            _ => craft::Control::Continue,
+                        };
+
+                        let mut recorder = Recorder::new();
+                        let mut game;
+                        let input: Result<&mut dyn Input, _> = if dry_run {
+                            Ok(&mut recorder)
+                        } else {
+                            match xiv::init() {
+                                Ok(handle) => {
+                                    game = GameInput::new(handle);
+                                    Ok(&mut game)
+                                }
+                                Err(e) => {
+                                    log::error!("{}", e);
+                                    Err((craft::CraftError::WindowLost, None, 0))
+                                }
+                            }
+                        };
+
+                        let result = input.and_then(|input| {
+                            let mut craft = craft::Crafter::new(
+                                input,
                                 &options,
                                 &macros,
                                 &tasks,
                                 status_fn,
                                 continue_fn,
-                            );
-
-                            // TODO: Do something useful with errors here.
-                            craft.unwrap().craft_items().unwrap();
+                                event_fn,
+                            )
+                            .with_inventory(inventory)
+                            .with_limits(&limits);
+                            craft.craft_items().map_err(|error| match craft.position() {
+                                Some((task, item)) => (error, Some(task.recipe.name.clone()), item),
+                                None => (error, None, 0),
+                            })
+                        });
+                        if dry_run {
+                            self.reply(Response::DryRun(recorder.finish()));
+                        }
+                        // There's nothing left to resume once every task is done,
+                        // or the user stopped and the client takes finished items
+                        // off the list. Anything else, such as the game going
+                        // away, leaves the checkpoint to be offered next time.
+                        let resumable = match &result {
+                            Ok(()) => false,
+                            Err((craft::CraftError::Cancelled, _, _)) => {
+                                !options.remove_finished_tasks
+                            }
+                            Err(_) => true,
+                        };
+                        if let (Some(path), false) = (&checkpoint, resumable) {
+                            if let Err(e) = checkpoint::remove_checkpoint(path) {
+                                log::error!("failed to remove checkpoint: {}", e);
+                            }
+                        }
+                        if let Err((error, task, item)) = result {
+                            log::error!("crafting stopped: {}", error);
+                            self.reply(Response::CraftError { error, task, item });
                         }
                         self.reply(Response::Eow);
                     }
@@ -222,4 +358,82 @@ mod test {
 
         Ok(())
     }
+
+    // A dry run goes through the whole craft without a game window, so it works
+    // anywhere, and replies with the plan before finishing.
+    #[test]
+    fn worker_dry_run() -> Result<(), Error> {
+        const MACROS: &str = r#"
+            [[xiv_macro]]
+            name = "two steps"
+            durability = [ 40 ]
+            actions = """
+                /ac "Basic Touch" <wait.3>
+                /ac "Basic Synthesis"
+            """
+        "#;
+        let mut macros = Vec::new();
+        crate::macros::read_macros_from_buffer(MACROS, &mut macros)?;
+        let recipe = crate::recipe::Recipe {
+            name: "Cloud Pearl".to_string(),
+            job: 5,
+            ..Default::default()
+        };
+        let mut task = task::Task::new(recipe, 2);
+        task.macro_name = "two steps".to_string();
+        let mut options = config::Options::default();
+        options.gear[5] = 3;
+
+        let (tx, rx) = setup();
+        tx.send(Request::Craft(Box::new(CraftRequest {
+            options,
+            tasks: vec![task],
+            macros,
+            dry_run: true,
+            checkpoint: None,
+            inventory: None,
+            history: None,
+            limits: Default::default(),
+        })))?;
+
+        let mut plan = None;
+        let mut finished = 0;
+        loop {
+            match rx.recv()? {
+                Response::Craft(status) => finished = status[0].finished,
+                Response::DryRun(p) => plan = Some(p),
+                Response::Eow => break,
+                resp => panic!("unexpected response {:?}", resp),
+            }
+        }
+        assert_eq!(finished, 2);
+        let plan = plan.unwrap();
+        let sent: Vec<&str> = plan
+            .entries
+            .iter()
+            .map(|e| e.text.as_str())
+            .filter(|t| t.starts_with('/'))
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                "/gearset change 3",
+                "/ac \"Basic Touch\"",
+                "/ac \"Basic Synthesis\"",
+                "/ac \"Basic Touch\"",
+                "/ac \"Basic Synthesis\"",
+            ]
+        );
+        // The second action waits out the first one's <wait.3>, counted from
+        // when the Enter that sent it was done.
+        let at: Vec<_> = plan
+            .entries
+            .iter()
+            .filter(|e| e.text.starts_with("/ac"))
+            .map(|e| e.at)
+            .collect();
+        assert_eq!(at[1] - at[0], std::time::Duration::from_millis(3100));
+        assert!(plan.total > at[3]);
+        Ok(())
+    }
 }
@@ -0,0 +1,162 @@
+use chrono::{DateTime, Duration as ChronoDuration, NaiveTime, TimeZone};
+use std::time::{Duration, Instant, SystemTime};
+
+// Bounds on a craft, so it can stop cleanly at a set time or count and
+// leave the PC free. Tasks still left over stay in the list for next time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionLimits {
+    // The wall-clock time to be done by.
+    pub deadline: Option<SystemTime>,
+    // How long the craft may run for from when it starts.
+    pub max_duration: Option<Duration>,
+    // The most items to craft across every task.
+    pub max_crafts: Option<u32>,
+}
+
+// Which limit ended a session.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SessionEnd {
+    // The next synthesis wouldn't finish before the deadline or duration ran out.
+    Time,
+    Crafts,
+}
+
+impl SessionLimits {
+    pub fn is_limited(&self) -> bool {
+        self.deadline.is_some() || self.max_duration.is_some() || self.max_crafts.is_some()
+    }
+
+    // When a session started at |start|, or |start_time| on the wall clock,
+    // has to be done by, if it's limited by time at all.
+    pub fn end(&self, start: Instant, start_time: SystemTime) -> Option<Instant> {
+        let deadline = self.deadline.map(|deadline| {
+            // A deadline already behind us leaves no time at all.
+            start + deadline.duration_since(start_time).unwrap_or_default()
+        });
+        let duration = self.max_duration.map(|d| start + d);
+        match (deadline, duration) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+// Tracks what's left of a session while crafting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    end: Option<Instant>,
+    crafts_left: Option<u32>,
+}
+
+impl Session {
+    pub fn new(limits: &SessionLimits, start: Instant) -> Self {
+        Session {
+            end: limits.end(start, SystemTime::now()),
+            crafts_left: limits.max_crafts,
+        }
+    }
+
+    // An unlimited session.
+    pub fn unlimited() -> Self {
+        Session {
+            end: None,
+            crafts_left: None,
+        }
+    }
+
+    // How many more crafts that take |craft_ms| each can be started at |now|,
+    // along with the limit that has the least room, if any applies.
+    pub fn room(&self, now: Instant, craft_ms: u64) -> (u32, Option<SessionEnd>) {
+        let mut room = (u32::MAX, None);
+        if let Some(crafts) = self.crafts_left {
+            room = (crafts, Some(SessionEnd::Crafts));
+        }
+        if let Some(end) = self.end {
+            let left = end.saturating_duration_since(now).as_millis() as u64;
+            let fit = (left / craft_ms.max(1)).min(u64::from(u32::MAX)) as u32;
+            if fit < room.0 {
+                room = (fit, Some(SessionEnd::Time));
+            }
+        }
+        room
+    }
+
+    pub fn add_crafts(&mut self, count: u32) {
+        if let Some(left) = &mut self.crafts_left {
+            *left = left.saturating_sub(count);
+        }
+    }
+}
+
+// The next time of day |text| comes around after |now|, where |text| is a
+// 24 hour "HH:MM" time. A time that has passed today means tomorrow.
+pub fn parse_deadline<Tz: TimeZone>(text: &str, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let time = NaiveTime::parse_from_str(text.trim(), "%H:%M").ok()?;
+    let today = now
+        .timezone()
+        .from_local_datetime(&now.date_naive().and_time(time))
+        .earliest()?;
+    if today > *now {
+        Some(today)
+    } else {
+        Some(today + ChronoDuration::days(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn room() {
+        let start = Instant::now();
+        let start_time = SystemTime::now();
+        let limits = SessionLimits {
+            deadline: Some(start_time + Duration::from_secs(100)),
+            max_duration: Some(Duration::from_secs(60)),
+            max_crafts: Some(10),
+        };
+        // The duration runs out before the deadline.
+        assert_eq!(
+            limits.end(start, start_time),
+            Some(start + Duration::from_secs(60))
+        );
+
+        let mut session = Session {
+            end: limits.end(start, start_time),
+            crafts_left: limits.max_crafts,
+        };
+        assert_eq!(session.room(start, 20000), (3, Some(SessionEnd::Time)));
+        assert_eq!(session.room(start, 5000), (10, Some(SessionEnd::Crafts)));
+        session.add_crafts(9);
+        assert_eq!(session.room(start, 5000), (1, Some(SessionEnd::Crafts)));
+        // A craft that can't finish in the 10 seconds left isn't started.
+        let late = start + Duration::from_secs(50);
+        assert_eq!(session.room(late, 20000), (0, Some(SessionEnd::Time)));
+
+        assert_eq!(Session::unlimited().room(late, 20000), (u32::MAX, None));
+        // A deadline that's passed leaves no time.
+        let passed = SessionLimits {
+            deadline: Some(start_time - Duration::from_secs(1)),
+            ..Default::default()
+        };
+        assert_eq!(passed.end(start, start_time), Some(start));
+    }
+
+    #[test]
+    fn deadline() {
+        let now = Utc.with_ymd_and_hms(2020, 3, 1, 22, 15, 0).unwrap();
+        assert_eq!(
+            parse_deadline("23:00", &now),
+            Some(Utc.with_ymd_and_hms(2020, 3, 1, 23, 0, 0).unwrap())
+        );
+        // Earlier times are tomorrow's.
+        assert_eq!(
+            parse_deadline(" 7:30 ", &now),
+            Some(Utc.with_ymd_and_hms(2020, 3, 2, 7, 30, 0).unwrap())
+        );
+        assert_eq!(parse_deadline("25:00", &now), None);
+        assert_eq!(parse_deadline("soon", &now), None);
+    }
+}
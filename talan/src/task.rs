@@ -1,6 +1,10 @@
-use crate::macros::Macro;
+use crate::config::Options;
+use crate::craft::{QUICK_SYNTHESIS_BATCH, QUICK_SYNTHESIS_MS};
+use crate::history::Timings;
+use crate::macros::{get_macro_for_recipe, Macro, Step};
 use crate::recipe::Recipe;
 use serde::{Deserialize, Serialize};
+use std::cmp::min;
 
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
 pub struct MaterialCount {
@@ -8,43 +12,247 @@ pub struct MaterialCount {
     pub hq: u32,
 }
 
+// How a task's HQ materials are shared out between its crafts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum HqSpread {
+    // Every craft uses the split in |Task::mat_quality|.
+    #[default]
+    Fixed,
+    // The HQ materials on hand go into the first crafts, as many as each takes.
+    FrontLoaded,
+    // The HQ materials on hand are shared out as evenly as possible.
+    Even,
+}
+
+impl HqSpread {
+    pub const ALL: [HqSpread; 3] = [HqSpread::Fixed, HqSpread::FrontLoaded, HqSpread::Even];
+
+    // The HQ units craft |craft| of |crafts| gets out of |hq|, with each
+    // craft taking |per_craft| units of the material.
+    fn hq_for(self, hq: u32, per_craft: u32, crafts: u32, craft: u32) -> u32 {
+        let hq = min(hq, per_craft * crafts);
+        match self {
+            HqSpread::Fixed => 0,
+            HqSpread::FrontLoaded => min(per_craft, hq.saturating_sub(craft * per_craft)),
+            HqSpread::Even => {
+                let extra = (craft < hq % crafts.max(1)) as u32;
+                hq / crafts.max(1) + extra
+            }
+        }
+    }
+}
+
 // A task represents crafting a specific item a given number of times
 // using a provided recipe and macro.
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
 pub struct Task {
     pub specify_materials: bool,
-    pub macro_id: usize,
+    // Macros are referenced by name so that editing the macro file doesn't
+    // reassign them.
+    #[serde(default)]
+    pub macro_name: String,
+    // Set when the user picked the macro, so reloads keep it instead of
+    // selecting one for the recipe.
+    #[serde(default)]
+    pub macro_override: bool,
+    // Craft with the crafting log's Quick Synthesis instead of a macro.
+    #[serde(default)]
+    pub quick_synthesis: bool,
+    // Run the macro |quantity| times as trial syntheses, which use no
+    // materials and make nothing, to try it out on the recipe.
+    #[serde(default)]
+    pub trial_synthesis: bool,
+    // Set by the user for collectables the recipe doesn't mark as one.
+    #[serde(default)]
+    pub collectable: bool,
+    // Milliseconds to wait after accepting a collectable for the crafting
+    // log to come back.
+    #[serde(default = "default_collectable_delay")]
+    pub collectable_delay: i32,
+    // Keeps the task where it is in the list when the order is optimized.
+    #[serde(default)]
+    pub pinned: bool,
     pub mat_quality: Vec<MaterialCount>,
+    // With a spread other than Fixed, the NQ and HQ of each material on hand
+    // for the task, which the crafts' HQ counts are worked out from.
+    #[serde(default)]
+    pub hq_spread: HqSpread,
+    #[serde(default)]
+    pub mat_stock: Vec<MaterialCount>,
     pub quantity: u32, // number of items to craft
     pub recipe: Recipe,
     #[serde(default)]
     pub estimate: u32,
 }
 
+fn default_collectable_delay() -> i32 {
+    2000
+}
+
 impl Task {
     pub fn new(recipe: Recipe, count: u32) -> Task {
         Task {
             specify_materials: false,
-            macro_id: 0,
+            macro_name: String::new(),
+            macro_override: false,
+            quick_synthesis: false,
+            trial_synthesis: false,
+            collectable: false,
+            collectable_delay: default_collectable_delay(),
+            pinned: false,
             quantity: count,
             mat_quality: recipe
                 .mats
                 .iter()
                 .map(|m| MaterialCount { nq: m.count, hq: 0 })
                 .collect(),
+            hq_spread: HqSpread::Fixed,
+            mat_stock: vec![MaterialCount::default(); recipe.mats.len()],
             recipe,
             estimate: 0,
         }
     }
 
-    pub fn update_estimate(&mut self, macros: &[Macro]) {
-        // 5 extra seconds of padding per craft is to conservatively cover the UI navigation per item.
-        self.estimate = self.quantity
-            * (macros[self.macro_id]
-                .actions
-                .iter()
-                .fold(0, |acc, action| acc + action.wait_ms) as u32
-                + 5000);
+    // The position of the task's macro in |macros|, or None if it no longer exists.
+    pub fn macro_index(&self, macros: &[Macro]) -> Option<usize> {
+        macros.iter().position(|m| m.name == self.macro_name)
+    }
+
+    // Selects the best macro for the recipe, unless the user picked one.
+    pub fn select_macro(&mut self, macros: &[Macro], options: &Options, timings: &Timings) {
+        if !self.macro_override {
+            // Leaving the name empty blocks the task until a macro is picked.
+            self.macro_name = get_macro_for_recipe(macros, &self.recipe, options)
+                .map(|i| macros[i].name.clone())
+                .unwrap_or_default();
+        }
+        self.update_estimate(macros, timings);
+    }
+
+    // The NQ and HQ counts of each material for craft |craft| of the task,
+    // counting from 0.
+    pub fn materials_for(&self, craft: u32) -> Vec<MaterialCount> {
+        self.recipe
+            .mats
+            .iter()
+            .enumerate()
+            .map(|(i, mat)| {
+                if self.hq_spread == HqSpread::Fixed {
+                    return self.mat_quality.get(i).copied().unwrap_or(MaterialCount {
+                        nq: mat.count,
+                        hq: 0,
+                    });
+                }
+                let stock = self.mat_stock.get(i).copied().unwrap_or_default();
+                let hq = self
+                    .hq_spread
+                    .hq_for(stock.hq, mat.count, self.quantity, craft);
+                MaterialCount {
+                    nq: mat.count - hq,
+                    hq,
+                }
+            })
+            .collect()
+    }
+
+    // The HQ count of material |mat| over the task's crafts, as runs of
+    // (HQ per craft, crafts).
+    pub fn hq_runs(&self, mat: usize) -> Vec<(u32, u32)> {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for craft in 0..self.quantity {
+            let hq = self.materials_for(craft)[mat].hq;
+            match runs.last_mut() {
+                Some((last, n)) if *last == hq => *n += 1,
+                _ => runs.push((hq, 1)),
+            }
+        }
+        runs
+    }
+
+    // How many of the task's crafts the stock on hand covers when its HQ is
+    // spread from the stock. The HQ each craft gets already fits the stock, so
+    // it's the NQ that runs out. Tasks that don't spread HQ aren't limited.
+    pub fn stock_craftable(&self) -> u32 {
+        if !self.specify_materials
+            || self.hq_spread == HqSpread::Fixed
+            || self.trial_synthesis
+            || self.quick_synthesis
+        {
+            return u32::MAX;
+        }
+        let mut used = vec![MaterialCount::default(); self.recipe.mats.len()];
+        for craft in 0..self.quantity {
+            for (i, needed) in self.materials_for(craft).iter().enumerate() {
+                let stock = self.mat_stock.get(i).copied().unwrap_or_default();
+                used[i].nq += needed.nq;
+                used[i].hq += needed.hq;
+                if used[i].nq > stock.nq || used[i].hq > stock.hq {
+                    return craft;
+                }
+            }
+        }
+        u32::MAX
+    }
+
+    // Takes |count| finished crafts off the task, along with the materials
+    // they used from the stock, so the rest are spread the same way. Trial
+    // syntheses use no materials, so the stock is left alone.
+    pub fn finish_crafts(&mut self, count: u32) {
+        let count = min(count, self.quantity);
+        if self.hq_spread != HqSpread::Fixed && !self.trial_synthesis {
+            let used: Vec<Vec<MaterialCount>> =
+                (0..count).map(|craft| self.materials_for(craft)).collect();
+            for materials in used {
+                for (stock, used) in self.mat_stock.iter_mut().zip(materials) {
+                    stock.nq = stock.nq.saturating_sub(used.nq);
+                    stock.hq = stock.hq.saturating_sub(used.hq);
+                }
+            }
+        }
+        self.quantity -= count;
+    }
+
+    // Whether the crafted items are collectables, which have to be accepted
+    // after each synthesis.
+    pub fn is_collectable(&self) -> bool {
+        self.collectable || self.recipe.collectable
+    }
+
+    // Whether the game allows the recipe to be quick synthesized.
+    pub fn can_quick_synthesize(&self) -> bool {
+        !self.recipe.expert && !self.is_collectable()
+    }
+
+    // Estimates the task from how long crafts of the recipe with the same
+    // macro have taken before, or failing that from the macro itself.
+    pub fn update_estimate(&mut self, macros: &[Macro], timings: &Timings) {
+        if self.quick_synthesis {
+            let batches = self.quantity.div_ceil(QUICK_SYNTHESIS_BATCH);
+            // Each batch also needs the recipe selected and the results dismissed.
+            self.estimate = self.quantity * QUICK_SYNTHESIS_MS as u32 + batches * 5000;
+            return;
+        }
+        // The macro may have been removed from the file, leaving nothing to estimate with.
+        let m = match self.macro_index(macros).map(|i| &macros[i]) {
+            Some(m) => m,
+            None => {
+                self.estimate = 0;
+                return;
+            }
+        };
+        let craft_ms = m
+            .steps
+            .iter()
+            .take_while(|step| **step != Step::Finish)
+            .fold(0, |acc, step| acc + step.duration_ms());
+        if let Some(ms) = timings.craft_ms(self.recipe.id, &m.name) {
+            self.estimate = self.quantity * ms as u32;
+            return;
+        }
+        // Without a history, 5 extra seconds of padding per craft is to
+        // conservatively cover the UI navigation per item.
+        let padding = timings.navigation_ms().unwrap_or(5000);
+        self.estimate = self.quantity * (craft_ms + padding) as u32;
     }
 }
 
@@ -55,6 +263,10 @@ pub struct Status {
     pub name: String,
     pub finished: u32,
     pub total: u32,
+    // Finished items that were accepted as collectables.
+    pub collectables: u32,
+    // Set when the task was cut short because the inventory ran out.
+    pub out_of_materials: bool,
 }
 
 impl<'a> From<&'a Task> for Status {
@@ -63,6 +275,192 @@ impl<'a> From<&'a Task> for Status {
             name: task.recipe.name.clone(),
             finished: 0,
             total: task.quantity as u32,
+            collectables: 0,
+            out_of_materials: false,
         }
     }
 }
+
+// A task for tests, crafting |quantity| of a recipe called |name| for |job|
+// from |mats|, given as material names and how many of each a craft takes.
+#[cfg(test)]
+pub fn test_task(name: &str, job: u32, mats: &[(&str, u32)], quantity: u32) -> Task {
+    let recipe = Recipe {
+        name: name.to_string(),
+        job,
+        result_amount: 1,
+        mats: mats
+            .iter()
+            .map(|&(name, count)| crate::recipe::RecipeMaterial {
+                count,
+                name: name.to_string(),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    Task::new(recipe, quantity)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HqSpread, MaterialCount, Task};
+    use crate::config::Options;
+    use crate::craft::{QUICK_SYNTHESIS_BATCH, QUICK_SYNTHESIS_MS};
+    use crate::history::{ActionTiming, CraftRecord, Outcome, Timings};
+    use crate::macros::read_macros_from_buffer;
+    use crate::recipe::{Recipe, RecipeMaterial};
+
+    const MACROS: &str = r#"
+        [[xiv_macro]]
+        name = "first"
+        durability = [ 40 ]
+        actions = "/ac Basic Synthesis"
+
+        [[xiv_macro]]
+        name = "second"
+        durability = [ 80 ]
+        actions = "/ac Basic Synthesis"
+    "#;
+
+    #[test]
+    fn macro_override() {
+        let mut macros = Vec::new();
+        read_macros_from_buffer(MACROS, &mut macros).unwrap();
+        let options = Options::default();
+        let timings = Timings::default();
+        let recipe = Recipe {
+            durability: 80,
+            ..Default::default()
+        };
+        let mut task = Task::new(recipe, 1);
+        task.select_macro(&macros, &options, &timings);
+        assert_eq!(task.macro_name, "second");
+
+        // Reordering the macros must not change the selection.
+        macros.reverse();
+        assert_eq!(task.macro_index(&macros), Some(0));
+
+        // A manual choice survives re-selection, even when the macro is gone.
+        task.macro_name = "first".to_string();
+        task.macro_override = true;
+        task.select_macro(&macros, &options, &timings);
+        assert_eq!(task.macro_name, "first");
+        macros.retain(|m| m.name != "first");
+        task.select_macro(&macros, &options, &timings);
+        assert_eq!(task.macro_index(&macros), None);
+        assert_eq!(task.estimate, 0);
+    }
+
+    #[test]
+    fn quick_synthesis_estimate() {
+        let mut task = Task::new(Recipe::default(), QUICK_SYNTHESIS_BATCH + 1);
+        task.quick_synthesis = true;
+        task.update_estimate(&[], &Timings::default());
+        // Two batches are needed, and no macro.
+        assert_eq!(
+            task.estimate,
+            (QUICK_SYNTHESIS_BATCH + 1) * QUICK_SYNTHESIS_MS as u32 + 2 * 5000
+        );
+
+        task.recipe.collectable = true;
+        assert!(!task.can_quick_synthesize());
+    }
+
+    #[test]
+    fn history_estimate() {
+        let mut macros = Vec::new();
+        read_macros_from_buffer(MACROS, &mut macros).unwrap();
+        let recipe = Recipe {
+            id: 7,
+            durability: 80,
+            ..Default::default()
+        };
+        let mut task = Task::new(recipe, 2);
+        task.select_macro(&macros, &Options::default(), &Timings::default());
+        let action_ms = macros[1].steps[0].duration_ms() as u32;
+        assert_eq!(task.estimate, 2 * (action_ms + 5000));
+
+        let record = |recipe_id, duration_ms| CraftRecord {
+            timestamp: 0,
+            recipe_id,
+            recipe_name: String::new(),
+            job: 0,
+            macro_name: "second".to_string(),
+            actions: vec![ActionTiming {
+                action: "Basic Synthesis".to_string(),
+                ms: 3000,
+            }],
+            duration_ms,
+            outcome: Outcome::Completed,
+        };
+        // Other recipes only tell us how long navigation takes.
+        let timings = Timings::new(&[record(8, 7000)]);
+        task.update_estimate(&macros, &timings);
+        assert_eq!(task.estimate, 2 * (action_ms + 4000));
+        // The recipe's own crafts are used as they are.
+        let timings = Timings::new(&[record(8, 7000), record(7, 9000), record(7, 11000)]);
+        task.update_estimate(&macros, &timings);
+        assert_eq!(task.estimate, 2 * 10000);
+    }
+
+    #[test]
+    fn hq_spread() {
+        let recipe = Recipe {
+            mats: vec![
+                RecipeMaterial {
+                    count: 2,
+                    name: "Ore".to_string(),
+                },
+                RecipeMaterial {
+                    count: 1,
+                    name: "Sand".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+        let mut task = Task::new(recipe, 4);
+        task.mat_stock[0] = MaterialCount { nq: 10, hq: 5 };
+        task.mat_stock[1] = MaterialCount { nq: 4, hq: 0 };
+
+        task.hq_spread = HqSpread::FrontLoaded;
+        assert_eq!(task.hq_runs(0), vec![(2, 2), (1, 1), (0, 1)]);
+        assert_eq!(task.hq_runs(1), vec![(0, 4)]);
+        task.hq_spread = HqSpread::Even;
+        assert_eq!(task.hq_runs(0), vec![(2, 1), (1, 3)]);
+        assert_eq!(
+            task.materials_for(1),
+            vec![
+                MaterialCount { nq: 1, hq: 1 },
+                MaterialCount { nq: 1, hq: 0 }
+            ]
+        );
+
+        // Crafts stop where the NQ of either material runs out.
+        task.specify_materials = true;
+        assert_eq!(task.stock_craftable(), u32::MAX);
+        task.mat_stock[0].nq = 2;
+        assert_eq!(task.stock_craftable(), 3);
+        task.mat_stock[0].nq = 10;
+        task.mat_stock[1].nq = 2;
+        assert_eq!(task.stock_craftable(), 2);
+        task.mat_stock[1].nq = 4;
+
+        // Finishing the first craft leaves 4 HQ ore for the other 3.
+        task.finish_crafts(1);
+        assert_eq!(task.quantity, 3);
+        assert_eq!(task.mat_stock[0], MaterialCount { nq: 10, hq: 3 });
+        assert_eq!(task.hq_runs(0), vec![(1, 3)]);
+
+        // Trials use nothing from the stock.
+        task.trial_synthesis = true;
+        task.finish_crafts(1);
+        assert_eq!(task.quantity, 2);
+        assert_eq!(task.mat_stock[0], MaterialCount { nq: 10, hq: 3 });
+        task.trial_synthesis = false;
+
+        // The fixed split is the same for every craft.
+        task.hq_spread = HqSpread::Fixed;
+        task.mat_quality[0] = MaterialCount { nq: 0, hq: 2 };
+        assert_eq!(task.hq_runs(0), vec![(2, 2)]);
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the watched files are checked. The GUI polls every frame, so this
+// keeps us from hitting the filesystem at the frame rate.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Detects changes to a set of files by polling their modification times. A
+// file being created or removed also counts as a change.
+#[derive(Debug)]
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    next_poll: Instant,
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        FileWatcher {
+            files: Vec::new(),
+            next_poll: Instant::now(),
+        }
+    }
+}
+
+impl FileWatcher {
+    // Replaces the watched files, taking their current state as unchanged.
+    pub fn watch(&mut self, files: Vec<PathBuf>) {
+        self.files = files
+            .into_iter()
+            .map(|path| {
+                let time = modified(&path);
+                (path, time)
+            })
+            .collect();
+    }
+
+    // Returns true if any watched file changed since the last check. Checks
+    // at most once per POLL_INTERVAL.
+    pub fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next_poll {
+            return false;
+        }
+        self.next_poll = now + POLL_INTERVAL;
+        self.check()
+    }
+
+    fn check(&mut self) -> bool {
+        let mut changed = false;
+        for (path, time) in &mut self.files {
+            let current = modified(path);
+            if current != *time {
+                log::debug!("{} changed", path.display());
+                *time = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileWatcher;
+    use anyhow::Result;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn detects_changes() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("talan-watch-{}.toml", std::process::id()));
+        std::fs::write(&path, "")?;
+        let mut watcher = FileWatcher::default();
+        watcher.watch(vec![path.clone()]);
+        assert!(!watcher.check());
+
+        let file = std::fs::OpenOptions::new().write(true).open(&path)?;
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))?;
+        assert!(watcher.check());
+        assert!(!watcher.check());
+
+        std::fs::remove_file(&path)?;
+        assert!(watcher.check());
+        Ok(())
+    }
+}
@@ -3,11 +3,14 @@ pub mod ui;
 use std::fmt;
 
 use anyhow::{anyhow, Error, Result};
-use std::ffi::CStr;
-use winapi::shared::basetsd::LONG_PTR;
-use winapi::shared::minwindef::BOOL;
-use winapi::shared::windef::HWND;
-use winapi::um::winuser::{EnumWindows, GetWindowTextA};
+#[cfg(windows)]
+use {
+    std::ffi::CStr,
+    winapi::shared::basetsd::LONG_PTR,
+    winapi::shared::minwindef::BOOL,
+    winapi::shared::windef::HWND,
+    winapi::um::winuser::{EnumWindows, GetWindowTextA, IsWindow},
+};
 
 pub const JOB_CNT: usize = 8;
 pub const JOBS: [&str; JOB_CNT] = ["CRP", "BSM", "ARM", "GSM", "LTW", "WVR", "ALC", "CUL"];
@@ -16,14 +19,21 @@ pub const JOBS: [&str; JOB_CNT] = ["CRP", "BSM", "ARM", "GSM", "LTW", "WVR", "AL
 // private to avoid leaking any winapi dependencies to callers.
 #[derive(Copy, Clone)]
 pub struct XivHandle {
-    hwnd: HWND,                    // The handle passed back by the winapi
+    #[cfg(windows)]
+    hwnd: HWND, // The handle passed back by the winapi
     pub use_slow_navigation: bool, // Add more delay to XIV navigation
 }
 
 impl fmt::Debug for XivHandle {
+    #[cfg(windows)]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Xivhandle {{ {} }}", self.hwnd as LONG_PTR as u64)
     }
+
+    #[cfg(not(windows))]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Xivhandle {{ }}")
+    }
 }
 
 #[cfg(windows)]
@@ -44,10 +54,27 @@ pub fn init() -> Result<XivHandle, Error> {
     }
 }
 
+// The game only runs on Windows, so there's never a window to find elsewhere.
+#[cfg(not(windows))]
+pub fn init() -> Result<XivHandle, Error> {
+    Err(anyhow!("Final Fantasy XIV can only be controlled on Windows."))
+}
+
+// Whether the game window behind |xiv_handle| still exists.
+pub fn window_exists(_xiv_handle: XivHandle) -> bool {
+    #[cfg(windows)]
+    unsafe {
+        IsWindow(_xiv_handle.hwnd) != 0
+    }
+    #[cfg(not(windows))]
+    true
+}
+
 // This callback is called for every window the user32 EnumWindows call finds
 // while walking the window list. It's used to find the XIV window by title.
 //
 // To be more foolproof checking process name might be better.
+#[cfg(windows)]
 unsafe extern "system" fn enum_callback(win_hwnd: HWND, arg: LONG_PTR) -> BOOL {
     let mut title: Vec<i8> = vec![0; 256];
     let xiv_hwnd = arg as *mut HWND;
@@ -1,7 +1,11 @@
 #[cfg(windows)]
-use {winapi::shared::minwindef::UINT, winapi::um::winuser::PostMessageA};
+use {
+    winapi::shared::minwindef::UINT,
+    winapi::um::winuser::{PostMessageA, PostMessageW},
+};
 
 use log;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -10,13 +14,17 @@ use std::time::Duration;
 // Delay for WM_CHAR events. In testing, even with low fps or
 // higher latency this value is still safe because of the game's
 // input buffer.
-const CHAR_DELAY: f32 = 0.05;
+pub const CHAR_DELAY: f32 = 0.05;
 // Delay for window navigation sent via KEYDOWN / KEYUP events.
 // These are affected by latency and in testing 200 milliseconds
 // seems safe in laggier conditions.
-const UI_DELAY: f32 = 0.1;
+pub const UI_DELAY: f32 = 0.1;
 const UI_DELAY_SLOW: f32 = 0.2;
 
+// The number of inputs the game window refused, so callers can tell input
+// has stopped reaching the game.
+static INPUT_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
 #[cfg(windows)]
 mod constants {
     use winapi::um::winuser::*;
@@ -180,7 +188,12 @@ pub fn clear_window(xiv_handle: super::XivHandle) {
 
 pub fn send_char(xiv_handle: super::XivHandle, c: char) {
     log::trace!("char: {}", c);
-    send_msg(xiv_handle, constants::MSG_KEY_CHAR, c as i32);
+    // Characters are posted as UTF-16 so that non-ASCII text, such as action
+    // names on a Japanese client, arrives intact.
+    let mut units = [0; 2];
+    for unit in c.encode_utf16(&mut units) {
+        send_wide_msg(xiv_handle, constants::MSG_KEY_CHAR, *unit);
+    }
     // TODO: Redo this when we have a better timing system
     wait(CHAR_DELAY);
 }
@@ -196,10 +209,30 @@ pub fn send_key(xiv_handle: super::XivHandle, c: i32) {
     }
 }
 
+// The number of inputs that failed to post since the program started.
+pub fn input_failures() -> usize {
+    INPUT_FAILURES.load(Ordering::Relaxed)
+}
+
+#[cfg(windows)]
+fn record_input(posted: bool) {
+    if !posted {
+        log::error!("failed to send input to the game window");
+        INPUT_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 // Send a character/key to the XIV window
 fn send_msg(_xiv_handle: super::XivHandle, _msg: u32, _key: i32) {
     #[cfg(windows)]
     unsafe {
-        PostMessageA(_xiv_handle.hwnd, _msg as UINT, _key as usize, 0);
+        record_input(PostMessageA(_xiv_handle.hwnd, _msg as UINT, _key as usize, 0) != 0);
+    }
+}
+
+fn send_wide_msg(_xiv_handle: super::XivHandle, _msg: u32, _unit: u16) {
+    #[cfg(windows)]
+    unsafe {
+        record_input(PostMessageW(_xiv_handle.hwnd, _msg as UINT, _unit as usize, 0) != 0);
     }
 }
@@ -31,6 +31,13 @@ pub struct RecipeLevelTable {
     pub SuggestedCraftsmanship: u32,
 }
 
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ItemResult {
+    #[serde(default)]
+    pub IsCollectable: u32,
+}
+
 #[allow(non_snake_case)]
 #[derive(Clone, Debug, Deserialize)]
 pub struct GameContentLinks {
@@ -55,6 +62,10 @@ pub struct ApiRecipe {
     pub DurabilityFactor: u32,
     pub QualityFactor: u32,
     pub IsSpecializationRequired: u32,
+    #[serde(default)]
+    pub IsExpert: u32,
+    #[serde(default)]
+    pub ItemResult: ItemResult,
     pub ItemIngredient0: ItemIngredient,
     pub ItemIngredient1: ItemIngredient,
     pub ItemIngredient2: ItemIngredient,
@@ -168,6 +179,8 @@ pub fn query_recipe(item_name: &str) -> Result<Vec<ApiRecipe>, Error> {
         "Name",
         "QualityFactor",
         "IsSpecializationRequired",
+        "IsExpert",
+        "ItemResult.IsCollectable",
         "RecipeLevelTable",
         "GameContentLinks",
     ];